@@ -0,0 +1,3065 @@
+//! Add keys to a [Lokalise](https://lokalise.com) project in bulk.
+//!
+//! The `lokalise-bulk-add-keys` binary is a thin wrapper around this. To talk to Lokalise from
+//! your own code, create a [`LokaliseClient`]:
+//!
+//! ```
+//! use lokalise_bulk_add_keys::{ClientConfig, LokaliseClient, ProxyConfig};
+//! use std::time::Duration;
+//! # use std::io::{Read, Write};
+//! #
+//! # fn mock_lokalise() -> String {
+//! #     let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+//! #     let url = format!("http://{}/api2", listener.local_addr().unwrap());
+//! #     std::thread::spawn(move || {
+//! #         let (mut stream, _) = listener.accept().unwrap();
+//! #         stream.read(&mut [0; 4096]).unwrap();
+//! #         let body = r#"{"projects":[{"project_id":"123.abc","name":"My app"}]}"#;
+//! #         write!(
+//! #             stream,
+//! #             "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+//! #             body.len(),
+//! #             body
+//! #         )
+//! #         .unwrap();
+//! #     });
+//! #     url
+//! # }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> anyhow::Result<()> {
+//! // A mock server that stands in for Lokalise and has a single project
+//! let base_url = mock_lokalise();
+//!
+//! let client = LokaliseClient::new(
+//!     "my-api-token".parse().unwrap(),
+//!     ClientConfig {
+//!         base_url,
+//!         max_retries: 3,
+//!         timeout: Duration::from_secs(30),
+//!         proxy: ProxyConfig::Disabled,
+//!         max_idle_connections: 8,
+//!         headers: vec![],
+//!     },
+//! )?;
+//!
+//! let projects = client.projects().await?;
+//! assert_eq!(projects[0].name, "My app");
+//! for project in projects {
+//!     println!("{} ({})", project.name, project.id);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Keys parsed from input files may have their translation in a separate `translation_file`, so
+//! call [`Data::resolve_files`] before creating them.
+
+use anyhow::{Error, Result};
+use futures::{stream, StreamExt, TryStreamExt};
+use indicatif::ProgressBar;
+use log::debug;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+    Client, Method, Proxy, RequestBuilder, Response, StatusCode, Url,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde::{Deserialize, Deserializer};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    convert::TryFrom,
+    fmt,
+    future::Future,
+    io,
+    num::{NonZeroU32, NonZeroUsize},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The prefixes printed in front of keys to say what happened to them.
+#[derive(Debug, Clone, Copy)]
+pub struct Markers {
+    pub created: &'static str,
+    pub updated: &'static str,
+    pub skipped: &'static str,
+    pub failed: &'static str,
+    pub deleted: &'static str,
+    pub renamed: &'static str,
+    pub missing: &'static str,
+    /// Appended to the message saying there were no keys
+    pub nothing: &'static str,
+}
+
+impl Markers {
+    pub const EMOJI: Markers = Markers {
+        created: "✅",
+        updated: "🔄",
+        skipped: "⏭️",
+        failed: "❌",
+        deleted: "🗑️",
+        renamed: "✏️",
+        missing: "❓",
+        nothing: " 👀",
+    };
+
+    /// For terminals and log viewers that don't show emoji.
+    pub const ASCII: Markers = Markers {
+        created: "[OK]",
+        updated: "[UPDATED]",
+        skipped: "[SKIPPED]",
+        failed: "[FAIL]",
+        deleted: "[DELETED]",
+        renamed: "[RENAMED]",
+        missing: "[MISSING]",
+        nothing: "",
+    };
+}
+
+/// What happened to each of the keys in the input file.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub created: Vec<KeyWithId>,
+    pub updated: Vec<KeyWithId>,
+    pub skipped: Vec<String>,
+    pub failed: Vec<FailedKey>,
+    /// How long talking to Lokalise took, if it was measured
+    pub elapsed: Option<Duration>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyWithId {
+    pub key: String,
+    pub key_id: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedKey {
+    pub key: String,
+    /// Why Lokalise rejected the key, if it said
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Summary {
+    pub fn len(&self) -> usize {
+        self.created.len() + self.updated.len() + self.skipped.len() + self.failed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn merge(&mut self, other: Summary) {
+        self.created.extend(other.created);
+        self.updated.extend(other.updated);
+        self.skipped.extend(other.skipped);
+        self.failed.extend(other.failed);
+    }
+
+    /// Print what happened to each key, and with `show_ids` also the ids of the keys that were
+    /// created or updated. Notes such as how many keys were skipped go to stderr.
+    pub fn print(&self, show_ids: bool, markers: Markers) {
+        if self.is_empty() {
+            eprintln!("No keys to create to seems{}", markers.nothing);
+            return;
+        }
+
+        let with_id = |key: &KeyWithId| {
+            if show_ids {
+                format!("{} ({})", key.key, key.key_id)
+            } else {
+                key.key.clone()
+            }
+        };
+
+        for key in &self.created {
+            println!("{} {}", markers.created, with_id(key))
+        }
+
+        for key in &self.updated {
+            println!("{} {}", markers.updated, with_id(key))
+        }
+
+        for key in &self.skipped {
+            println!("{} {}", markers.skipped, key)
+        }
+
+        for failed in &self.failed {
+            match &failed.reason {
+                Some(reason) => println!("{} {}: {}", markers.failed, failed.key, reason),
+                None => println!("{} {}", markers.failed, failed.key),
+            }
+        }
+
+        if !self.skipped.is_empty() {
+            eprintln!(
+                "{} of {} keys already exist and were skipped",
+                self.skipped.len(),
+                self.len()
+            );
+        }
+
+        if let Some(elapsed) = self.elapsed {
+            eprintln!(
+                "Took {:.1}s, {:.1} keys created per second",
+                elapsed.as_secs_f64(),
+                self.keys_per_second(elapsed)
+            );
+        }
+    }
+
+    fn keys_per_second(&self, elapsed: Duration) -> f64 {
+        let secs = elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.created.len() as f64 / secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Put the keys in the order they have in the input file, so runs are easy to compare even
+    /// though creating and updating happen separately.
+    pub fn sort(&mut self, input_order: &HashMap<String, usize>) {
+        let position = |key: &String| input_order.get(key).copied().unwrap_or(usize::MAX);
+        self.created.sort_by_key(|created| position(&created.key));
+        self.updated.sort_by_key(|updated| position(&updated.key));
+        self.skipped.sort_by_key(position);
+        self.failed.sort_by_key(|failed| position(&failed.key));
+    }
+
+    pub fn print_counts(&self) {
+        println!(
+            "Created {}, updated {}, skipped {}, failed {}",
+            self.created.len(),
+            self.updated.len(),
+            self.skipped.len(),
+            self.failed.len()
+        );
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = json!({
+            "created": &self.created,
+            "updated": &self.updated,
+            "skipped": &self.skipped,
+            "failed": &self.failed,
+            "counts": {
+                "created": self.created.len(),
+                "updated": self.updated.len(),
+                "skipped": self.skipped.len(),
+                "failed": self.failed.len(),
+                "total": self.len(),
+            },
+        });
+        if let Some(elapsed) = self.elapsed {
+            json["elapsed_ms"] = json!(elapsed.as_millis() as u64);
+            json["keys_per_second"] = json!(self.keys_per_second(elapsed));
+        }
+        json
+    }
+}
+
+/// What happened to each of the keys in the input files of `delete`.
+#[derive(Debug, Default)]
+pub struct DeleteSummary {
+    pub deleted: Vec<String>,
+    /// Keys that aren't in the project
+    pub missing: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+impl DeleteSummary {
+    pub fn merge(&mut self, other: DeleteSummary) {
+        self.deleted.extend(other.deleted);
+        self.missing.extend(other.missing);
+        self.failed.extend(other.failed);
+    }
+
+    pub fn print(&self, markers: Markers) {
+        if self.deleted.is_empty() && self.missing.is_empty() && self.failed.is_empty() {
+            eprintln!("No keys to delete it seems{}", markers.nothing);
+            return;
+        }
+
+        for key in &self.deleted {
+            println!("{} {}", markers.deleted, key)
+        }
+
+        for key in &self.missing {
+            println!("{} {} (not in the project)", markers.missing, key)
+        }
+
+        for key in &self.failed {
+            println!("{} {}", markers.failed, key)
+        }
+    }
+
+    pub fn print_dry_run(&self) {
+        let sections = [
+            ("Would delete", &self.deleted),
+            ("Not in the project", &self.missing),
+        ];
+
+        for (title, keys) in sections.iter() {
+            if keys.is_empty() {
+                continue;
+            }
+
+            println!("{} ({}):", title, keys.len());
+            for key in keys.iter() {
+                println!("  {}", key);
+            }
+        }
+    }
+
+    pub fn print_counts(&self) {
+        println!(
+            "Deleted {}, not in the project {}, failed {}",
+            self.deleted.len(),
+            self.missing.len(),
+            self.failed.len()
+        );
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "deleted": &self.deleted,
+            "missing": &self.missing,
+            "failed": &self.failed,
+            "counts": {
+                "deleted": self.deleted.len(),
+                "missing": self.missing.len(),
+                "failed": self.failed.len(),
+            },
+        })
+    }
+}
+
+/// A key to give a new name with `rename`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+/// What happened to each of the keys in the input file of `rename`.
+#[derive(Debug, Default)]
+pub struct RenameSummary {
+    pub renamed: Vec<Rename>,
+    /// Keys that aren't in the project
+    pub missing: Vec<String>,
+    pub failed: Vec<FailedKey>,
+}
+
+impl RenameSummary {
+    pub fn print(&self, markers: Markers) {
+        if self.renamed.is_empty() && self.missing.is_empty() && self.failed.is_empty() {
+            eprintln!("No keys to rename it seems{}", markers.nothing);
+            return;
+        }
+
+        for rename in &self.renamed {
+            println!("{} {} -> {}", markers.renamed, rename.from, rename.to)
+        }
+
+        for key in &self.missing {
+            println!("{} {} (not in the project)", markers.missing, key)
+        }
+
+        for failed in &self.failed {
+            match &failed.reason {
+                Some(reason) => println!("{} {}: {}", markers.failed, failed.key, reason),
+                None => println!("{} {}", markers.failed, failed.key),
+            }
+        }
+    }
+
+    pub fn print_dry_run(&self) {
+        if !self.renamed.is_empty() {
+            println!("Would rename ({}):", self.renamed.len());
+            for rename in &self.renamed {
+                println!("  {} -> {}", rename.from, rename.to);
+            }
+        }
+
+        if !self.missing.is_empty() {
+            println!("Not in the project ({}):", self.missing.len());
+            for key in &self.missing {
+                println!("  {}", key);
+            }
+        }
+    }
+
+    pub fn print_counts(&self) {
+        println!(
+            "Renamed {}, not in the project {}, failed {}",
+            self.renamed.len(),
+            self.missing.len(),
+            self.failed.len()
+        );
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "renamed": &self.renamed,
+            "missing": &self.missing,
+            "failed": &self.failed,
+            "counts": {
+                "renamed": self.renamed.len(),
+                "missing": self.missing.len(),
+                "failed": self.failed.len(),
+            },
+        })
+    }
+}
+
+/// Wrapper around the API token that keeps it out of `Debug` output.
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub struct ApiToken(String);
+
+impl fmt::Debug for ApiToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ApiToken(<redacted>)")
+    }
+}
+
+impl From<String> for ApiToken {
+    fn from(token: String) -> Self {
+        ApiToken(token)
+    }
+}
+
+impl FromStr for ApiToken {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(ApiToken(s.to_string()))
+    }
+}
+
+/// The CLDR plural categories used by a language, if we know them.
+///
+/// Regional variants such as `pt_BR` use the categories of the base language.
+pub fn plural_categories(language_iso: &str) -> Option<&'static [&'static str]> {
+    const ONE_OTHER: &[&str] = &["one", "other"];
+    const ONE_MANY_OTHER: &[&str] = &["one", "many", "other"];
+    const ONE_FEW_MANY_OTHER: &[&str] = &["one", "few", "many", "other"];
+    const ONE_FEW_OTHER: &[&str] = &["one", "few", "other"];
+    const OTHER: &[&str] = &["other"];
+    const ALL: &[&str] = &["zero", "one", "two", "few", "many", "other"];
+
+    const CATEGORIES: &[(&str, &[&str])] = &[
+        ("ar", ALL),
+        ("bg", ONE_OTHER),
+        ("bs", ONE_FEW_OTHER),
+        ("cs", ONE_FEW_MANY_OTHER),
+        ("cy", ALL),
+        ("da", ONE_OTHER),
+        ("de", ONE_OTHER),
+        ("el", ONE_OTHER),
+        ("en", ONE_OTHER),
+        ("es", ONE_MANY_OTHER),
+        ("et", ONE_OTHER),
+        ("fi", ONE_OTHER),
+        ("fr", ONE_MANY_OTHER),
+        ("ga", &["one", "two", "few", "many", "other"]),
+        ("he", &["one", "two", "other"]),
+        ("hi", ONE_OTHER),
+        ("hr", ONE_FEW_OTHER),
+        ("hu", ONE_OTHER),
+        ("id", OTHER),
+        ("is", ONE_OTHER),
+        ("it", ONE_MANY_OTHER),
+        ("ja", OTHER),
+        ("ko", OTHER),
+        ("lt", ONE_FEW_MANY_OTHER),
+        ("lv", &["zero", "one", "other"]),
+        ("ms", OTHER),
+        ("nb", ONE_OTHER),
+        ("nl", ONE_OTHER),
+        ("no", ONE_OTHER),
+        ("pl", ONE_FEW_MANY_OTHER),
+        ("pt", ONE_MANY_OTHER),
+        ("ro", ONE_FEW_OTHER),
+        ("ru", ONE_FEW_MANY_OTHER),
+        ("sk", ONE_FEW_MANY_OTHER),
+        ("sl", &["one", "two", "few", "other"]),
+        ("sr", ONE_FEW_OTHER),
+        ("sv", ONE_OTHER),
+        ("th", OTHER),
+        ("tr", ONE_OTHER),
+        ("uk", ONE_FEW_MANY_OTHER),
+        ("vi", OTHER),
+        ("zh", OTHER),
+    ];
+
+    let language = language_iso
+        .split(['_', '-'])
+        .next()
+        .unwrap_or(language_iso)
+        .to_lowercase();
+
+    CATEGORIES
+        .iter()
+        .find(|(iso, _)| *iso == language)
+        .map(|(_, categories)| *categories)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Data {
+    pub keys: Vec<KeyToAdd>,
+    /// Tags added to every key in the file
+    #[serde(
+        default,
+        deserialize_with = "deserialize_tags",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub default_tags: Vec<String>,
+    /// Values for the keys in the file that don't have their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<KeyDefaults>,
+}
+
+impl Data {
+    /// Replace the `translation_file`s of the keys with the contents of the files, which are
+    /// relative to `dir`.
+    pub async fn resolve_files(&mut self, dir: &Path) -> Result<()> {
+        for key in &mut self.keys {
+            if let Translation::File(path) = &key.translation {
+                let path = dir.join(path);
+                let text = tokio::fs::read_to_string(&path).await.map_err(|err| {
+                    Error::msg(format!(
+                        "Failed to read the translation of `{}` from {}: {}",
+                        key.key,
+                        path.display(),
+                        err
+                    ))
+                })?;
+                key.translation = Translation::Singular(text);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The fields of [`KeyToAdd`] that can be given once for a whole file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyDefaults {
+    /// Used for the keys without tags. Unlike `default_tags` these aren't added to the keys'
+    /// own tags
+    #[serde(
+        default,
+        deserialize_with = "deserialize_tags",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platforms: Option<Vec<Platform>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub char_limit: Option<NonZeroU32>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KeyToAdd {
+    pub key: String,
+    /// The name as it was in the input, if `key` was changed by `--key-prefix` and the like
+    #[serde(skip)]
+    pub original_key: Option<String>,
+    /// Names to use instead of `key` on some platforms when the key is created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform_names: Option<PlatformNames>,
+    #[serde(flatten)]
+    pub translation: Translation,
+    /// Translations for other languages than the base language, keyed by `language_iso`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub languages: BTreeMap<String, LanguageTranslation>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_tags",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub tags: Vec<String>,
+    /// Defaults to all of them, unless `--no-platforms` is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platforms: Option<Vec<Platform>>,
+    /// Whether `platforms` was filled in with the default instead of given in the input, in which
+    /// case updating the key leaves its platforms alone
+    #[serde(skip)]
+    pub default_platforms: bool,
+    /// Context for the translators
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Maximum number of characters the translations may have
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub char_limit: Option<NonZeroU32>,
+    /// Label for the count variable of a plural key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plural_name: Option<String>,
+    /// Comments to start the key's discussion with. Only added when the key is created
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<String>,
+    /// Image to attach to the key for context, after it's created. Only uploaded with
+    /// `--with-screenshots`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screenshot: Option<PathBuf>,
+    /// Hide the key from non-admin contributors
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub hidden: bool,
+    /// Archive the key so it isn't included in exports
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub archived: bool,
+}
+
+impl KeyToAdd {
+    /// Fill in the fields the key doesn't have from `defaults`.
+    pub fn apply_defaults(&mut self, defaults: &KeyDefaults) {
+        if self.tags.is_empty() {
+            self.tags = defaults.tags.clone();
+        }
+        if self.platforms.is_none() {
+            self.platforms = defaults.platforms.clone();
+        }
+        if self.description.is_none() {
+            self.description = defaults.description.clone();
+        }
+        if self.char_limit.is_none() {
+            self.char_limit = defaults.char_limit;
+        }
+    }
+
+    /// The name of the key on a platform, which is `key` unless it's overridden in
+    /// `platform_names`.
+    pub fn name_on(&self, platform: &Platform) -> &str {
+        let names = match &self.platform_names {
+            Some(names) => names,
+            None => return &self.key,
+        };
+        let name = match platform {
+            Platform::Ios => names.ios.as_ref(),
+            Platform::Android => names.android.as_ref(),
+            Platform::Web => names.web.as_ref(),
+            Platform::Other => names.other.as_ref(),
+            Platform::Custom(platform) => names.custom.get(platform),
+        };
+        name.map_or(&self.key, |name| name)
+    }
+
+    /// The built-in platforms, and the custom ones the key is on or has a name for.
+    pub fn known_platforms(&self) -> Vec<Platform> {
+        let mut platforms = Platform::all();
+        let custom =
+            self.platforms
+                .iter()
+                .flatten()
+                .cloned()
+                .chain(self.platform_names.iter().flat_map(|names| {
+                    names
+                        .custom
+                        .keys()
+                        .map(|platform| Platform::Custom(platform.clone()))
+                }));
+        for platform in custom {
+            if !platforms.contains(&platform) {
+                platforms.push(platform);
+            }
+        }
+        platforms
+    }
+
+    /// The names of the key on all of its [`known_platforms`](Self::known_platforms), without
+    /// duplicates.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = vec![];
+        for platform in self.known_platforms() {
+            let name = self.name_on(&platform);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PlatformNames {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ios: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub android: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub other: Option<String>,
+    /// Names on custom platforms, keyed by platform
+    #[serde(flatten)]
+    pub custom: BTreeMap<String, String>,
+}
+
+impl PlatformNames {
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        vec![
+            self.ios.as_mut(),
+            self.android.as_mut(),
+            self.web.as_mut(),
+            self.other.as_mut(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.custom.values_mut())
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Tags can be given either as a list or as a single string separated by `,` or `;`.
+fn deserialize_tags<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(
+        untagged,
+        expecting = "a list of tags or a string of tags separated by `,` or `;`"
+    )]
+    enum Tags {
+        List(Vec<String>),
+        Joined(String),
+    }
+
+    Ok(match Tags::deserialize(deserializer)? {
+        Tags::List(tags) => tags,
+        Tags::Joined(tags) => tags
+            .split([',', ';'])
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| tag.to_string())
+            .collect(),
+    })
+}
+
+/// A platform keys can be on. Besides the four every project has, projects can have custom
+/// platforms with any name.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(from = "String", into = "String")]
+pub enum Platform {
+    Ios,
+    Android,
+    Web,
+    Other,
+    Custom(String),
+}
+
+impl From<String> for Platform {
+    fn from(platform: String) -> Self {
+        match platform.as_str() {
+            "ios" => Platform::Ios,
+            "android" => Platform::Android,
+            "web" => Platform::Web,
+            "other" => Platform::Other,
+            _ => Platform::Custom(platform),
+        }
+    }
+}
+
+impl From<Platform> for String {
+    fn from(platform: Platform) -> Self {
+        platform.as_str().to_string()
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().is_empty() {
+            return Err("The platform can't be empty".to_string());
+        }
+        Ok(Platform::from(s.to_string()))
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Platform {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Platform::Ios => "ios",
+            Platform::Android => "android",
+            Platform::Web => "web",
+            Platform::Other => "other",
+            Platform::Custom(platform) => platform,
+        }
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self, Platform::Custom(_))
+    }
+
+    /// The built-in platforms.
+    pub fn all() -> Vec<Platform> {
+        vec![
+            Platform::Ios,
+            Platform::Android,
+            Platform::Web,
+            Platform::Other,
+        ]
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum Translation {
+    #[serde(rename = "translation")]
+    Singular(String),
+    #[serde(rename = "translations")]
+    Plural(PluralForms),
+    /// Replaced by `Singular` with the file's contents by [`Data::resolve_files`]
+    #[serde(rename = "translation_file")]
+    File(PathBuf),
+}
+
+impl Translation {
+    pub fn is_plural(&self) -> bool {
+        match self {
+            Translation::Singular(_) | Translation::File(_) => false,
+            Translation::Plural(_) => true,
+        }
+    }
+
+    pub fn plural_forms(&self) -> Option<&PluralForms> {
+        match self {
+            Translation::Singular(_) | Translation::File(_) => None,
+            Translation::Plural(forms) => Some(forms),
+        }
+    }
+
+    /// Whether the text, or any of the plural forms, is empty or only whitespace.
+    pub fn has_empty_text(&self) -> bool {
+        match self {
+            Translation::Singular(text) => text.trim().is_empty(),
+            Translation::Plural(forms) => forms.has_empty_text(),
+            Translation::File(_) => false,
+        }
+    }
+
+    /// Fails for a `File` that hasn't been read with [`Data::resolve_files`].
+    pub fn to_json(&self, language_iso: &str) -> Result<serde_json::Value, LokaliseError> {
+        match self {
+            Translation::Singular(text) => Ok(json!({
+                "language_iso": language_iso,
+                "translation": text,
+            })),
+            Translation::Plural(forms) => Ok(json!({
+                "language_iso": language_iso,
+                "translation": forms,
+            })),
+            Translation::File(path) => {
+                Err(LokaliseError::UnreadTranslationFile { path: path.clone() })
+            }
+        }
+    }
+}
+
+/// Same as `Translation` but without the `translation`/`translations` wrapper, since the language
+/// is already given by the surrounding map.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum LanguageTranslation {
+    Singular(String),
+    Plural(PluralForms),
+}
+
+impl LanguageTranslation {
+    pub fn is_plural(&self) -> bool {
+        match self {
+            LanguageTranslation::Singular(_) => false,
+            LanguageTranslation::Plural(_) => true,
+        }
+    }
+
+    pub fn plural_forms(&self) -> Option<&PluralForms> {
+        match self {
+            LanguageTranslation::Singular(_) => None,
+            LanguageTranslation::Plural(forms) => Some(forms),
+        }
+    }
+
+    pub fn has_empty_text(&self) -> bool {
+        match self {
+            LanguageTranslation::Singular(text) => text.trim().is_empty(),
+            LanguageTranslation::Plural(forms) => forms.has_empty_text(),
+        }
+    }
+
+    pub fn to_json(&self, language_iso: &str) -> serde_json::Value {
+        match self {
+            LanguageTranslation::Singular(text) => json!({
+                "language_iso": language_iso,
+                "translation": text,
+            }),
+            LanguageTranslation::Plural(forms) => json!({
+                "language_iso": language_iso,
+                "translation": forms,
+            }),
+        }
+    }
+}
+
+pub const PLURAL_CATEGORIES: &[&str] = &["zero", "one", "two", "few", "many", "other"];
+
+/// The texts of a plural key, keyed by CLDR plural category.
+///
+/// `singular` and `plural` are accepted as aliases for `one` and `other`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(try_from = "BTreeMap<String, String>")]
+pub struct PluralForms(BTreeMap<String, String>);
+
+impl PluralForms {
+    pub fn new(singular: String, plural: String) -> Self {
+        let mut forms = BTreeMap::new();
+        forms.insert("one".to_string(), singular);
+        forms.insert("other".to_string(), plural);
+        PluralForms(forms)
+    }
+
+    pub fn has_empty_text(&self) -> bool {
+        self.0.values().any(|text| text.trim().is_empty())
+    }
+
+    /// The plural categories that have a text.
+    pub fn categories(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(|category| category.as_str())
+    }
+
+    /// The same texts but with the plural categories of another language, falling back to the
+    /// `other` text for categories that this doesn't have.
+    pub fn for_language(&self, language_iso: &str) -> Self {
+        let categories = match plural_categories(language_iso) {
+            Some(categories) => categories,
+            None => return PluralForms(self.0.clone()),
+        };
+
+        let forms = categories
+            .iter()
+            .map(|category| {
+                let text = self.0.get(*category).unwrap_or(&self.0["other"]);
+                (category.to_string(), text.clone())
+            })
+            .collect();
+        PluralForms(forms)
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for PluralForms {
+    type Error = String;
+
+    fn try_from(map: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        let mut forms = BTreeMap::new();
+
+        for (category, text) in map {
+            let category = match category.as_str() {
+                "singular" => "one".to_string(),
+                "plural" => "other".to_string(),
+                _ if PLURAL_CATEGORIES.contains(&category.as_str()) => category,
+                _ => {
+                    return Err(format!(
+                        "unknown plural category `{}`, expected one of {}",
+                        category,
+                        PLURAL_CATEGORIES.join(", ")
+                    ))
+                }
+            };
+
+            if forms.contains_key(&category) {
+                return Err(format!("plural category `{}` is given twice", category));
+            }
+            forms.insert(category, text);
+        }
+
+        if !forms.contains_key("other") {
+            return Err("plural translations must include `other` (or `plural`)".to_string());
+        }
+
+        Ok(PluralForms(forms))
+    }
+}
+
+#[derive(Debug)]
+pub struct LokaliseClient {
+    client: Client,
+    base_url: String,
+    max_retries: u32,
+    /// What the responses said about the rate limit, if they had the headers
+    rate_limit: Mutex<Option<RateLimit>>,
+}
+
+/// The `X-RateLimit-*` headers of the last response, with `remaining` counted down as requests
+/// are sent so concurrent requests don't all go by the same count.
+#[derive(Debug, Clone, Copy)]
+struct RateLimit {
+    limit: Option<u32>,
+    remaining: u32,
+    /// When the window ends and `limit` requests can be sent again, if Lokalise said
+    reset: Option<SystemTime>,
+    /// Set once `remaining` is for the next window, which requests have to wait for
+    not_before: Option<SystemTime>,
+}
+
+impl RateLimit {
+    /// Whether the window `remaining` was counted for is over, so it's no longer known.
+    fn has_ended(&self) -> bool {
+        match (self.not_before, self.reset) {
+            (Some(_), Some(reset)) => reset <= SystemTime::now(),
+            _ => false,
+        }
+    }
+}
+
+/// How to split up requests that create or update many keys.
+#[derive(Debug)]
+pub struct BatchOptions {
+    pub chunk_size: NonZeroUsize,
+    pub concurrency: NonZeroUsize,
+    /// Stop sending chunks once one of them had keys that failed, instead of sending all of them
+    pub fail_fast: bool,
+    /// Advanced as each chunk is done
+    pub progress: ProgressBar,
+    /// Set when a chunk failed with `fail_fast`
+    stopped: AtomicBool,
+}
+
+impl BatchOptions {
+    pub fn new(
+        chunk_size: NonZeroUsize,
+        concurrency: NonZeroUsize,
+        fail_fast: bool,
+        progress: ProgressBar,
+    ) -> Self {
+        Self {
+            chunk_size,
+            concurrency,
+            fail_fast,
+            progress,
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    /// Send a chunk of `len` keys, unless an earlier chunk failed with `fail_fast`.
+    ///
+    /// Chunks that Lokalise rejects as a whole count as failed rather than stopping everything,
+    /// but other errors such as being unauthorized or rate limited are returned as is.
+    async fn send_chunk<F>(&self, len: usize, send: F) -> Result<ChunkOutcome, LokaliseError>
+    where
+        F: Future<Output = Result<KeysResponse, LokaliseError>>,
+    {
+        if self.stopped.load(Ordering::SeqCst) {
+            self.progress.inc(len as u64);
+            return Ok(ChunkOutcome::NotSent);
+        }
+
+        let outcome = match send.await {
+            Ok(res) => ChunkOutcome::Sent(res),
+            Err(LokaliseError::Api { code, message }) => {
+                ChunkOutcome::Rejected(format!("Got {} {}", code, message))
+            }
+            Err(err) => return Err(err),
+        };
+        self.progress.inc(len as u64);
+
+        let failed = match &outcome {
+            ChunkOutcome::Sent(res) => res.keys.len() < len,
+            ChunkOutcome::Rejected(_) => true,
+            ChunkOutcome::NotSent => false,
+        };
+        if failed && self.fail_fast {
+            self.stopped.store(true, Ordering::SeqCst);
+        }
+
+        Ok(outcome)
+    }
+}
+
+#[derive(Debug)]
+enum ChunkOutcome {
+    Sent(KeysResponse),
+    /// Lokalise responded with an error for the whole chunk
+    Rejected(String),
+    /// Skipped because an earlier chunk failed
+    NotSent,
+}
+
+const NOT_SENT_REASON: &str = "Not sent because an earlier chunk failed";
+
+#[derive(Debug)]
+pub struct ClientConfig {
+    pub base_url: String,
+    pub max_retries: u32,
+    pub timeout: Duration,
+    pub proxy: ProxyConfig,
+    /// How many connections to Lokalise to keep open for reuse once they're idle
+    pub max_idle_connections: usize,
+    /// Extra headers to send with every request, as name and value
+    pub headers: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub enum ProxyConfig {
+    /// Use the `HTTPS_PROXY` and `HTTP_PROXY` env vars, if set
+    FromEnv,
+    Url(String),
+    Disabled,
+}
+
+impl LokaliseClient {
+    pub fn new(token: ApiToken, config: ClientConfig) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            let header = HeaderName::from_bytes(name.as_bytes())
+                .map_err(Error::from)
+                .and_then(|name| Ok((name, HeaderValue::from_str(value)?)));
+            let (name, value) =
+                header.map_err(|err| Error::msg(format!("Invalid header `{}`: {}", name, err)))?;
+            headers.insert(name, value);
+        }
+        let mut token = HeaderValue::from_str(&token.0)?;
+        token.set_sensitive(true);
+        headers.insert("x-api-token", token);
+        let mut builder = Client::builder()
+            .default_headers(headers)
+            .timeout(config.timeout)
+            .pool_max_idle_per_host(config.max_idle_connections);
+        builder = match config.proxy {
+            // reqwest picks up the env vars by default
+            ProxyConfig::FromEnv => builder,
+            ProxyConfig::Url(url) => builder.proxy(
+                Proxy::all(&url)
+                    .map_err(|err| Error::msg(format!("Invalid proxy URL `{}`: {}", url, err)))?,
+            ),
+            ProxyConfig::Disabled => builder.no_proxy(),
+        };
+        let client = builder.build()?;
+
+        Ok(Self {
+            client,
+            base_url: config.base_url.trim_end_matches('/').to_string(),
+            max_retries: config.max_retries,
+            rate_limit: Mutex::new(None),
+        })
+    }
+
+    /// Send a request, retrying it if Lokalise responds with 429 Too Many Requests or a 5xx server
+    /// error, or if it couldn't be reached, timed out, or dropped the connection.
+    ///
+    /// A POST that timed out, lost its connection, or got a server error may still have been
+    /// handled, so it's only retried if it couldn't connect at all or was rate limited. It's up to
+    /// the caller to find out what happened.
+    ///
+    /// Waits for as long as the `Retry-After` header says, or backs off exponentially if it's
+    /// missing.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, LokaliseError> {
+        let mut retries = 0;
+
+        loop {
+            self.throttle().await;
+
+            let request = request
+                .try_clone()
+                .ok_or(LokaliseError::UncloneableRequest)?
+                .build()?;
+            let method = request.method().clone();
+            let url = request.url().clone();
+            let idempotent = method != Method::POST;
+
+            debug!("{} {}", method, url);
+            let res = match self.client.execute(request).await {
+                Ok(res) => res,
+                Err(err)
+                    if (err.is_connect() || idempotent && is_transient(&err))
+                        && retries < self.max_retries =>
+                {
+                    let delay = backoff(retries);
+                    debug!(
+                        "{} {} failed, retrying in {:?}: {}",
+                        method, url, delay, err
+                    );
+                    tokio::time::delay_for(delay).await;
+                    retries += 1;
+                    continue;
+                }
+                Err(err) if err.is_timeout() => return Err(LokaliseError::Timeout { url }),
+                Err(err) => return Err(err.into()),
+            };
+            debug!("{} {} -> {}", method, url, res.status());
+            if let Some(new) = rate_limit(&res) {
+                let mut rate_limit = self.rate_limit.lock().unwrap();
+                *rate_limit = match *rate_limit {
+                    // Already counting requests against a later window
+                    Some(old) if old.reset > new.reset && !old.has_ended() => Some(old),
+                    // Responses to requests sent before others may come in later, with a count
+                    // that's too high
+                    Some(old) if old.reset == new.reset => Some(RateLimit {
+                        remaining: old.remaining.min(new.remaining),
+                        ..old
+                    }),
+                    _ => Some(new),
+                };
+            }
+
+            let status = res.status();
+            if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                return Ok(res);
+            }
+            if retries >= self.max_retries || status.is_server_error() && !idempotent {
+                return Err(if status.is_server_error() {
+                    LokaliseError::ServerError { status, retries }
+                } else {
+                    LokaliseError::RateLimited { retries }
+                });
+            }
+
+            let delay = retry_after(&res).unwrap_or_else(|| backoff(retries));
+            if status.is_server_error() {
+                debug!("Got {}, retrying in {:?}", status, delay);
+            } else {
+                debug!("Rate limited, retrying in {:?}", delay);
+            }
+            tokio::time::delay_for(delay).await;
+            retries += 1;
+        }
+    }
+
+    /// Wait before sending another request if it would use up the rate limit, until the window
+    /// resets. Otherwise count the request against the limit.
+    async fn throttle(&self) {
+        /// Left for other clients using the same token
+        const LOW_REMAINING: u32 = 1;
+        /// Lokalise's limit is per second, used when the responses don't say when it resets
+        const WINDOW: Duration = Duration::from_secs(1);
+        /// In case the reset is far off because of clock skew
+        const MAX_DELAY: Duration = Duration::from_secs(60);
+
+        let delay = {
+            let mut rate_limit = self.rate_limit.lock().unwrap();
+            let rate_limit = match &mut *rate_limit {
+                Some(rate_limit) if !rate_limit.has_ended() => rate_limit,
+                // The next response says what's left in the new window
+                _ => return,
+            };
+            let now = SystemTime::now();
+            let start = if rate_limit.remaining > LOW_REMAINING {
+                rate_limit.remaining -= 1;
+                rate_limit.not_before
+            } else {
+                // Send this as the first request of the next window
+                let reset = rate_limit
+                    .reset
+                    .filter(|reset| *reset > now)
+                    .unwrap_or(now + WINDOW);
+                rate_limit.remaining = rate_limit.limit.unwrap_or(1).saturating_sub(1);
+                rate_limit.reset = Some(reset + WINDOW);
+                rate_limit.not_before = Some(reset);
+                Some(reset)
+            };
+            match start.and_then(|start| start.duration_since(now).ok()) {
+                Some(delay) => delay.min(MAX_DELAY),
+                None => return,
+            }
+        };
+
+        debug!(
+            "About to be rate limited, waiting {:?} for the limit to reset",
+            delay
+        );
+        tokio::time::delay_for(delay).await;
+    }
+
+    /// Make a cheap request to find out if the token is wrong before doing any real work.
+    pub async fn check_token(&self) -> Result<(), LokaliseError> {
+        let res = self
+            .send(
+                self.client
+                    .get(&self.url("/projects"))
+                    .query(&[("limit", 1)]),
+            )
+            .await?;
+
+        if res.status() == StatusCode::UNAUTHORIZED {
+            Err(LokaliseError::Unauthorized)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn projects(&self) -> Result<Vec<Project>, LokaliseError> {
+        #[derive(Debug, Deserialize)]
+        struct ProjectsResponse {
+            projects: Vec<Project>,
+        }
+
+        let res = self.send(self.client.get(&self.url("/projects"))).await?;
+
+        Ok(response_json::<ProjectsResponse>(res).await?.projects)
+    }
+
+    pub async fn project(&self, project_id: &str) -> Result<Project, LokaliseError> {
+        let res = self
+            .send(
+                self.client
+                    .get(&self.url(&format!("/projects/{}", project_id))),
+            )
+            .await?;
+
+        response_json::<Project>(res).await
+    }
+
+    pub async fn languages(&self, project: &Project) -> Result<Vec<Language>, LokaliseError> {
+        #[derive(Debug, Deserialize)]
+        struct LanguagesResponse {
+            languages: Vec<Language>,
+        }
+
+        let res = self
+            .send(
+                self.client
+                    .get(&self.url(&format!("/projects/{}/languages", &project.id)))
+                    .query(&[("limit", 5000)]),
+            )
+            .await?;
+
+        Ok(response_json::<LanguagesResponse>(res).await?.languages)
+    }
+
+    /// The tags used by the keys in the project. Lokalise doesn't list the tags on their own, so
+    /// this fetches all the keys.
+    pub async fn tags(
+        &self,
+        project: &Project,
+        concurrency: NonZeroUsize,
+    ) -> Result<BTreeSet<String>, LokaliseError> {
+        Ok(project_tags(
+            &self.all_keys(project, concurrency, None).await?,
+        ))
+    }
+
+    pub async fn all_keys(
+        &self,
+        project: &Project,
+        concurrency: NonZeroUsize,
+        match_platform: Option<&Platform>,
+    ) -> Result<HashMap<String, ExistingKey>, LokaliseError> {
+        let mut key_names = HashMap::new();
+        self.for_each_keys_page(project, concurrency, &KeysQuery::default(), |keys| {
+            insert_key_names(&mut key_names, keys, match_platform)
+        })
+        .await?;
+        Ok(key_names)
+    }
+
+    /// The existing keys with any of these names. Much cheaper than
+    /// [`all_keys`](Self::all_keys) in big projects when there are only a few names, since
+    /// Lokalise does the filtering. The names can't contain commas.
+    pub async fn keys_named(
+        &self,
+        project: &Project,
+        names: &[&str],
+        match_platform: Option<&Platform>,
+    ) -> Result<HashMap<String, ExistingKey>, LokaliseError> {
+        let mut key_names = HashMap::new();
+        if names.is_empty() {
+            return Ok(key_names);
+        }
+
+        let query = KeysQuery {
+            filter_keys: Some(names.join(",")),
+            ..KeysQuery::default()
+        };
+        self.for_each_keys_page(project, NonZeroUsize::new(1).unwrap(), &query, |keys| {
+            insert_key_names(&mut key_names, keys, match_platform)
+        })
+        .await?;
+        Ok(key_names)
+    }
+
+    /// All the keys in the project as they'd be written in an input file, with the translations
+    /// in `language_iso` as their main translation and the non-empty ones in other languages in
+    /// `languages`. Sorted by name.
+    pub async fn export_keys(
+        &self,
+        project: &Project,
+        language_iso: &str,
+        concurrency: NonZeroUsize,
+    ) -> Result<Vec<KeyToAdd>, LokaliseError> {
+        let query = KeysQuery {
+            translations: true,
+            ..KeysQuery::default()
+        };
+        let mut keys = vec![];
+        self.for_each_keys_page(project, concurrency, &query, |page| {
+            keys.extend(page.into_iter().map(|key| exported_key(key, language_iso)))
+        })
+        .await?;
+        keys.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(keys)
+    }
+
+    /// Fetch all the pages of keys matching `query`, calling `on_keys` with each of them.
+    async fn for_each_keys_page<F>(
+        &self,
+        project: &Project,
+        concurrency: NonZeroUsize,
+        query: &KeysQuery,
+        mut on_keys: F,
+    ) -> Result<(), LokaliseError>
+    where
+        F: FnMut(Vec<KeyResponse>),
+    {
+        // Prefer cursor pagination. If Lokalise doesn't send a cursor back we got the first
+        // regular page instead, and continue with page based pagination.
+        let first_page = self
+            .keys_page(project, KeysPageParam::FirstCursor, query)
+            .await?;
+        let mut keys_count = first_page.keys.len();
+        let total_count = first_page.total_count;
+        let mut next_cursor = first_page.next_cursor;
+        let is_cursor_based = next_cursor.is_some();
+        on_keys(first_page.keys);
+
+        if is_cursor_based {
+            while let Some(cursor) = next_cursor.filter(|cursor| !cursor.is_empty()) {
+                let page = self
+                    .keys_page(project, KeysPageParam::Cursor(cursor), query)
+                    .await?;
+                next_cursor = page.next_cursor;
+                on_keys(page.keys);
+            }
+        } else if let Some(total_count) = total_count {
+            let page_count =
+                total_count / KEYS_PAGE_LIMIT + usize::from(total_count % KEYS_PAGE_LIMIT != 0);
+
+            let mut pages = stream::iter(2..=page_count)
+                .map(|page| self.keys_page(project, KeysPageParam::Page(page), query))
+                .buffer_unordered(concurrency.get());
+
+            while let Some(page) = pages.try_next().await? {
+                on_keys(page.keys);
+            }
+        } else {
+            // Lokalise didn't tell us how many keys there are so fetch the pages one by one until
+            // we get a page that isn't full
+            let mut page = 1;
+            while keys_count == KEYS_PAGE_LIMIT {
+                page += 1;
+                let keys = self
+                    .keys_page(project, KeysPageParam::Page(page), query)
+                    .await?
+                    .keys;
+                keys_count = keys.len();
+                on_keys(keys);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn keys_page(
+        &self,
+        project: &Project,
+        param: KeysPageParam,
+        keys_query: &KeysQuery,
+    ) -> Result<KeysPage, LokaliseError> {
+        let include_translations = if keys_query.translations { "1" } else { "0" };
+        let mut query = vec![
+            ("limit", KEYS_PAGE_LIMIT.to_string()),
+            ("include_translations", include_translations.to_string()),
+        ];
+        query.extend(
+            KEYS_PAGE_FIELDS
+                .iter()
+                .map(|(name, value)| (*name, value.to_string())),
+        );
+        if let Some(filter_keys) = &keys_query.filter_keys {
+            query.push(("filter_keys", filter_keys.clone()));
+        }
+        match param {
+            KeysPageParam::FirstCursor => {
+                debug!("Fetching first page of existing keys");
+                query.push(("pagination", "cursor".to_string()));
+            }
+            KeysPageParam::Cursor(cursor) => {
+                debug!("Fetching existing keys after cursor {}", cursor);
+                query.push(("pagination", "cursor".to_string()));
+                query.push(("cursor", cursor));
+            }
+            KeysPageParam::Page(page) => {
+                debug!("Fetching page {} of existing keys", page);
+                query.push(("page", page.to_string()));
+            }
+        }
+
+        let res = self
+            .send(
+                self.client
+                    .get(&self.url(&format!("/projects/{}/keys", &project.id)))
+                    .query(&query),
+            )
+            .await?;
+
+        let header = |name: &str| {
+            res.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+        let total_count = header("x-pagination-total-count").and_then(|value| value.parse().ok());
+        let next_cursor = header("x-pagination-next-cursor");
+        let keys = response_json::<KeysResponse>(res).await?.keys;
+
+        Ok(KeysPage {
+            keys,
+            total_count,
+            next_cursor,
+        })
+    }
+
+    pub async fn create_keys(
+        &self,
+        project: &Project,
+        language_iso: &str,
+        keys_to_create: &[KeyToAdd],
+        batch: &BatchOptions,
+    ) -> Result<Summary, LokaliseError> {
+        let mut created_keys = HashMap::new();
+        let mut reasons = HashMap::new();
+
+        let mut chunks = stream::iter(keys_to_create.chunks(batch.chunk_size.get()))
+            .map(|chunk| async move {
+                let send = self.create_chunk(project, language_iso, chunk);
+                let outcome = batch.send_chunk(chunk.len(), send).await?;
+                Ok::<_, LokaliseError>((chunk, outcome))
+            })
+            .buffer_unordered(batch.concurrency.get());
+        while let Some((chunk, outcome)) = chunks.try_next().await? {
+            let res = match outcome {
+                ChunkOutcome::Sent(res) => res,
+                ChunkOutcome::Rejected(reason) => {
+                    for key in chunk {
+                        reasons.insert(key.name_on(&Platform::Ios).to_string(), reason.clone());
+                    }
+                    continue;
+                }
+                ChunkOutcome::NotSent => {
+                    for key in chunk {
+                        let name = key.name_on(&Platform::Ios).to_string();
+                        reasons.insert(name, NOT_SENT_REASON.to_string());
+                    }
+                    continue;
+                }
+            };
+
+            created_keys.extend(
+                res.keys
+                    .into_iter()
+                    .map(|key| (key.key_name.ios, key.key_id)),
+            );
+            for error in res.errors {
+                if let Some(key_name) = error.key.as_ref().and_then(|key| key.key_name.as_ref()) {
+                    reasons.insert(key_name.name().to_string(), error.message);
+                }
+            }
+        }
+
+        let mut summary = Summary::default();
+        for key in keys_to_create {
+            if let Some(&key_id) = created_keys.get(key.name_on(&Platform::Ios)) {
+                summary.created.push(KeyWithId {
+                    key: key.key.clone(),
+                    key_id,
+                });
+            } else {
+                summary.failed.push(FailedKey {
+                    key: key.key.clone(),
+                    reason: reasons.remove(key.name_on(&Platform::Ios)),
+                });
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Create a chunk of keys. If the request fails after it may have reached Lokalise, the keys
+    /// that were created anyway are looked up and only the others are sent again, so they aren't
+    /// reported as already taken.
+    async fn create_chunk(
+        &self,
+        project: &Project,
+        language_iso: &str,
+        chunk: &[KeyToAdd],
+    ) -> Result<KeysResponse, LokaliseError> {
+        let payload = create_payload(language_iso, chunk)?;
+        let mut pending = chunk
+            .iter()
+            .map(|key| key.name_on(&Platform::Ios))
+            .zip(payload["keys"].as_array().cloned().unwrap_or_default())
+            .collect::<Vec<_>>();
+        let mut created = vec![];
+        let mut retries = 0;
+
+        loop {
+            let keys = pending.iter().map(|(_, key)| key).collect::<Vec<_>>();
+            let res = self
+                .send(
+                    self.client
+                        .post(&self.url(&format!("/projects/{}/keys", &project.id)))
+                        .json(&json!({ "keys": keys })),
+                )
+                .await;
+            let err = match res {
+                Ok(res) => {
+                    let mut res = parse_keys_response(res).await?;
+                    res.keys.extend(created);
+                    return Ok(res);
+                }
+                Err(err) if is_unconfirmed(&err) && retries < self.max_retries => err,
+                Err(LokaliseError::ServerError { status, .. }) => {
+                    return Err(LokaliseError::ServerError { status, retries })
+                }
+                Err(err) => return Err(err),
+            };
+
+            let delay = backoff(retries);
+            debug!(
+                "Creating keys failed, checking which were created and retrying in {:?}: {}",
+                delay, err
+            );
+            tokio::time::delay_for(delay).await;
+            retries += 1;
+
+            let names = pending.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+            let found = self.keys_with_ios_names(project, &names).await?;
+            pending.retain(|(name, _)| !found.iter().any(|key| key.key_name.ios == *name));
+            created.extend(found);
+            if pending.is_empty() {
+                return Ok(KeysResponse {
+                    keys: created,
+                    errors: vec![],
+                });
+            }
+        }
+    }
+
+    /// The keys with these names on iOS, which is the name keys being created are matched by.
+    async fn keys_with_ios_names(
+        &self,
+        project: &Project,
+        names: &[&str],
+    ) -> Result<Vec<KeyResponse>, LokaliseError> {
+        let query = KeysQuery {
+            // Names with commas can't be filtered on, so then all the keys are fetched
+            filter_keys: (!names.iter().any(|name| name.contains(','))).then(|| names.join(",")),
+            ..KeysQuery::default()
+        };
+        let mut found = vec![];
+        self.for_each_keys_page(project, NonZeroUsize::new(1).unwrap(), &query, |keys| {
+            found.extend(
+                keys.into_iter()
+                    .filter(|key| names.contains(&key.key_name.ios.as_str())),
+            )
+        })
+        .await?;
+        Ok(found)
+    }
+
+    /// Update the translations, tags, and so on of keys that already exist, given their key ids.
+    pub async fn update_keys(
+        &self,
+        project: &Project,
+        language_iso: &str,
+        keys_to_update: &[(u64, KeyToAdd)],
+        batch: &BatchOptions,
+    ) -> Result<Summary, LokaliseError> {
+        let mut updated_keys = HashSet::new();
+        let mut reasons = HashMap::new();
+
+        let mut chunks = stream::iter(keys_to_update.chunks(batch.chunk_size.get()))
+            .map(|chunk| async move {
+                let send = self.update_chunk(project, language_iso, chunk);
+                let outcome = batch.send_chunk(chunk.len(), send).await?;
+                Ok::<_, LokaliseError>((chunk, outcome))
+            })
+            .buffer_unordered(batch.concurrency.get());
+        while let Some((chunk, outcome)) = chunks.try_next().await? {
+            let res = match outcome {
+                ChunkOutcome::Sent(res) => res,
+                ChunkOutcome::Rejected(reason) => {
+                    for (key_id, _) in chunk {
+                        reasons.insert(*key_id, reason.clone());
+                    }
+                    continue;
+                }
+                ChunkOutcome::NotSent => {
+                    for (key_id, _) in chunk {
+                        reasons.insert(*key_id, NOT_SENT_REASON.to_string());
+                    }
+                    continue;
+                }
+            };
+
+            updated_keys.extend(res.keys.into_iter().map(|key| key.key_id));
+            for error in res.errors {
+                if let Some(key_id) = error.key.as_ref().and_then(|key| key.key_id) {
+                    reasons.insert(key_id, error.message);
+                }
+            }
+        }
+
+        let mut summary = Summary::default();
+        for (key_id, key) in keys_to_update {
+            if updated_keys.contains(key_id) {
+                summary.updated.push(KeyWithId {
+                    key: key.key.clone(),
+                    key_id: *key_id,
+                });
+            } else {
+                summary.failed.push(FailedKey {
+                    key: key.key.clone(),
+                    reason: reasons.remove(key_id),
+                });
+            }
+        }
+
+        Ok(summary)
+    }
+
+    async fn update_chunk(
+        &self,
+        project: &Project,
+        language_iso: &str,
+        chunk: &[(u64, KeyToAdd)],
+    ) -> Result<KeysResponse, LokaliseError> {
+        let payload = update_payload(language_iso, chunk)?;
+        let res = self
+            .send(
+                self.client
+                    .put(&self.url(&format!("/projects/{}/keys", &project.id)))
+                    .json(&payload),
+            )
+            .await?;
+
+        parse_keys_response(res).await
+    }
+
+    /// Upload a PNG or JPEG screenshot and attach it to a key.
+    pub async fn upload_screenshot(
+        &self,
+        project: &Project,
+        key_id: u64,
+        title: &str,
+        mime_type: &str,
+        image: &[u8],
+    ) -> Result<(), LokaliseError> {
+        #[derive(Debug, Deserialize)]
+        struct ScreenshotsResponse {
+            #[serde(default)]
+            errors: Vec<ErrorResponseInner>,
+        }
+
+        let payload = json!({
+            "screenshots": [{
+                "data": format!("data:{};base64,{}", mime_type, base64::encode(image)),
+                "title": title,
+                "key_ids": [key_id],
+            }],
+        });
+
+        let res = self
+            .send(
+                self.client
+                    .post(&self.url(&format!("/projects/{}/screenshots", &project.id)))
+                    .json(&payload),
+            )
+            .await?;
+
+        match response_json::<ScreenshotsResponse>(res)
+            .await?
+            .errors
+            .into_iter()
+            .next()
+        {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Delete keys given their ids. Keys are only reported as deleted if Lokalise removed the
+    /// whole chunk they were sent in.
+    pub async fn delete_keys(
+        &self,
+        project: &Project,
+        keys_to_delete: &[(u64, String)],
+        chunk_size: NonZeroUsize,
+    ) -> Result<DeleteSummary, LokaliseError> {
+        #[derive(Debug, Deserialize)]
+        struct DeleteResponse {
+            keys_removed: bool,
+            #[serde(default)]
+            keys_locked: usize,
+        }
+
+        let mut summary = DeleteSummary::default();
+
+        for chunk in keys_to_delete.chunks(chunk_size.get()) {
+            let payload = json!({
+                "keys": chunk.iter().map(|(key_id, _)| key_id).collect::<Vec<_>>(),
+            });
+
+            let res = self
+                .send(
+                    self.client
+                        .delete(&self.url(&format!("/projects/{}/keys", &project.id)))
+                        .json(&payload),
+                )
+                .await?;
+            let res = response_json::<DeleteResponse>(res).await?;
+
+            let names = chunk.iter().map(|(_, key)| key.clone());
+            if res.keys_removed && res.keys_locked == 0 {
+                summary.deleted.extend(names);
+            } else {
+                summary.failed.extend(names);
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Give keys new names. The new name is used on every platform, replacing any names the key
+    /// had on specific platforms.
+    pub async fn rename_keys(
+        &self,
+        project: &Project,
+        renames: &[(u64, Rename)],
+        chunk_size: NonZeroUsize,
+    ) -> Result<RenameSummary, LokaliseError> {
+        let mut summary = RenameSummary::default();
+
+        for chunk in renames.chunks(chunk_size.get()) {
+            let payload = json!({
+                "keys": chunk
+                    .iter()
+                    .map(|(key_id, rename)| json!({ "key_id": key_id, "key_name": &rename.to }))
+                    .collect::<Vec<_>>(),
+            });
+
+            let res = self
+                .send(
+                    self.client
+                        .put(&self.url(&format!("/projects/{}/keys", &project.id)))
+                        .json(&payload),
+                )
+                .await?;
+            let res = response_json::<KeysResponse>(res).await?;
+
+            let renamed = res
+                .keys
+                .iter()
+                .map(|key| key.key_id)
+                .collect::<HashSet<_>>();
+            let mut reasons = res
+                .errors
+                .into_iter()
+                .filter_map(|error| Some((error.key?.key_id?, error.message)))
+                .collect::<HashMap<_, _>>();
+            for (key_id, rename) in chunk {
+                if renamed.contains(key_id) {
+                    summary.renamed.push(rename.clone());
+                } else {
+                    summary.failed.push(FailedKey {
+                        key: rename.from.clone(),
+                        reason: reasons.remove(key_id),
+                    });
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+
+    fn url(&self, url: &str) -> String {
+        format!("{}{}", self.base_url, url)
+    }
+}
+
+/// The body of the request creating a chunk of keys.
+pub fn create_payload(
+    language_iso: &str,
+    chunk: &[KeyToAdd],
+) -> Result<serde_json::Value, LokaliseError> {
+    let keys = chunk
+        .iter()
+        .map(|key| {
+            let mut payload = key_payload(language_iso, key)?;
+            let key_name = match &key.platform_names {
+                Some(_) => json!(key
+                    .known_platforms()
+                    .iter()
+                    .map(|platform| (platform.to_string(), json!(key.name_on(platform))))
+                    .collect::<serde_json::Map<_, _>>()),
+                None => json!(&key.key),
+            };
+            payload.insert("key_name".to_string(), key_name);
+            if !key.comments.is_empty() {
+                let comments = key
+                    .comments
+                    .iter()
+                    .map(|comment| json!({ "comment": comment }))
+                    .collect::<Vec<_>>();
+                payload.insert("comments".to_string(), json!(comments));
+            }
+            Ok(payload)
+        })
+        .collect::<Result<Vec<_>, LokaliseError>>()?;
+    Ok(json!({ "keys": keys }))
+}
+
+/// The body of the request updating a chunk of keys, given their key ids.
+pub fn update_payload(
+    language_iso: &str,
+    chunk: &[(u64, KeyToAdd)],
+) -> Result<serde_json::Value, LokaliseError> {
+    let keys = chunk
+        .iter()
+        .map(|(key_id, key)| {
+            let mut payload = key_payload(language_iso, key)?;
+            payload.insert("key_id".to_string(), json!(key_id));
+            if key.default_platforms {
+                payload.remove("platforms");
+            }
+            Ok(payload)
+        })
+        .collect::<Result<Vec<_>, LokaliseError>>()?;
+    Ok(json!({ "keys": keys }))
+}
+
+/// The parts of a key's payload that are the same whether it's being created or updated.
+fn key_payload(
+    language_iso: &str,
+    key: &KeyToAdd,
+) -> Result<serde_json::Map<String, serde_json::Value>, LokaliseError> {
+    let mut translations = vec![key.translation.to_json(language_iso)?];
+    for (language_iso, translation) in &key.languages {
+        translations.push(translation.to_json(language_iso));
+    }
+
+    let mut payload = serde_json::Map::new();
+    payload.insert("translations".to_string(), json!(translations));
+    payload.insert("is_plural".to_string(), json!(key.translation.is_plural()));
+    if let Some(platforms) = &key.platforms {
+        payload.insert("platforms".to_string(), json!(platforms));
+    }
+    payload.insert("tags".to_string(), json!(&key.tags));
+    if let Some(description) = &key.description {
+        payload.insert("description".to_string(), json!(description));
+    }
+    if let Some(char_limit) = key.char_limit {
+        payload.insert("char_limit".to_string(), json!(char_limit));
+    }
+    if let Some(plural_name) = &key.plural_name {
+        payload.insert("plural_name".to_string(), json!(plural_name));
+    }
+    if key.hidden {
+        payload.insert("is_hidden".to_string(), json!(true));
+    }
+    if key.archived {
+        payload.insert("is_archived".to_string(), json!(true));
+    }
+    Ok(payload)
+}
+
+/// Things that can go wrong when talking to Lokalise.
+#[derive(Debug, thiserror::Error)]
+pub enum LokaliseError {
+    #[error("Lokalise request failed\nGot 401 unauthorized. Please ensure your auth token is correct and has both read and write permissions")]
+    Unauthorized,
+    #[error("Lokalise request failed\nGot 404 {message}")]
+    NotFound { message: String },
+    #[error("Lokalise request failed\nStill rate limited after retrying {retries} times. Try increasing `--max-retries`")]
+    RateLimited { retries: u32 },
+    #[error("Lokalise request failed\nLokalise server error ({status}), gave up after {retries} retries")]
+    ServerError { status: StatusCode, retries: u32 },
+    #[error("Lokalise request failed\nGot {code} {message}")]
+    Api { code: u32, message: String },
+    #[error("Failed to parse lokalise response\nGot {status} with body: {snippet}")]
+    Parse { status: StatusCode, snippet: String },
+    #[error("Request to {url} timed out. Try increasing `--timeout`")]
+    Timeout { url: Url },
+    #[error("Failed to clone request")]
+    UncloneableRequest,
+    #[error("The translation file {} hasn't been read, use `Data::resolve_files` first", .path.display())]
+    UnreadTranslationFile { path: PathBuf },
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+impl From<ErrorResponseInner> for LokaliseError {
+    fn from(error: ErrorResponseInner) -> Self {
+        match error.code {
+            401 => LokaliseError::Unauthorized,
+            404 => LokaliseError::NotFound {
+                message: error.message,
+            },
+            _ if error.message == "Unauthorized" => LokaliseError::Unauthorized,
+            code => LokaliseError::Api {
+                code,
+                message: error.message,
+            },
+        }
+    }
+}
+
+/// Lokalise responds with 200 even when some of the keys couldn't be created or updated, listing
+/// them in `errors` next to the keys that were. So the body is only an error response if it doesn't
+/// have `keys` at all.
+async fn parse_keys_response(res: Response) -> Result<KeysResponse, LokaliseError> {
+    response_json(res).await
+}
+
+/// Parse the body of a response, including the status and the start of the body in the error if
+/// it isn't what we expected.
+async fn response_json<T: DeserializeOwned>(res: Response) -> Result<T, LokaliseError> {
+    let status = res.status();
+    let body = res.text().await?;
+    serde_json::from_str(&body).map_err(|_| match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(ErrorResponse { error }) => error.into(),
+        Err(_) => unexpected_response(status, &body),
+    })
+}
+
+fn unexpected_response(status: StatusCode, body: &str) -> LokaliseError {
+    const MAX_SNIPPET_CHARS: usize = 200;
+
+    let mut snippet = body
+        .trim()
+        .chars()
+        .take(MAX_SNIPPET_CHARS)
+        .collect::<String>();
+    if body.trim().chars().count() > MAX_SNIPPET_CHARS {
+        snippet.push('…');
+    }
+    if snippet.is_empty() {
+        snippet.push_str("<empty body>");
+    }
+
+    LokaliseError::Parse { status, snippet }
+}
+
+const KEYS_PAGE_LIMIT: usize = 1000;
+
+/// Lokalise can't be asked for only some fields of the keys, but it can leave out the biggest
+/// ones. Only the ids, names, and tags are used, so everything else that can be left out is.
+/// Translations are also left out unless a [`KeysQuery`] asks for them.
+const KEYS_PAGE_FIELDS: &[(&str, &str)] = &[
+    ("include_comments", "0"),
+    ("include_screenshots", "0"),
+    ("disable_references", "1"),
+];
+
+/// Which keys to fetch, and what to fetch about them.
+#[derive(Debug, Default)]
+struct KeysQuery {
+    /// Only the keys with these comma separated names
+    filter_keys: Option<String>,
+    /// Also fetch the translations, which are most of the data
+    translations: bool,
+}
+
+#[derive(Debug)]
+enum KeysPageParam {
+    FirstCursor,
+    Cursor(String),
+    Page(usize),
+}
+
+/// A page of keys, along with the pagination headers Lokalise included.
+#[derive(Debug)]
+struct KeysPage {
+    keys: Vec<KeyResponse>,
+    total_count: Option<usize>,
+    next_cursor: Option<String>,
+}
+
+/// Keys can have different names per platform, so record all of them. An incoming key then counts
+/// as existing if it collides with the name on any platform.
+fn insert_key_names(
+    key_names: &mut HashMap<String, ExistingKey>,
+    keys: Vec<KeyResponse>,
+    match_platform: Option<&Platform>,
+) {
+    for key in keys {
+        let existing = ExistingKey {
+            id: key.key_id,
+            tags: key.tags,
+        };
+        for (platform, name) in key.key_name.into_names() {
+            if match_platform.is_none() || match_platform == Some(&platform) {
+                key_names.insert(name, existing.clone());
+            }
+        }
+    }
+}
+
+fn retry_after(res: &Response) -> Option<Duration> {
+    let seconds = res
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+fn rate_limit(res: &Response) -> Option<RateLimit> {
+    let header =
+        |name: &str| -> Option<u64> { res.headers().get(name)?.to_str().ok()?.parse().ok() };
+
+    Some(RateLimit {
+        limit: header("x-ratelimit-limit").and_then(|limit| u32::try_from(limit).ok()),
+        remaining: u32::try_from(header("x-ratelimit-remaining")?).ok()?,
+        // Seconds since the epoch
+        reset: header("x-ratelimit-reset").map(|reset| UNIX_EPOCH + Duration::from_secs(reset)),
+        not_before: None,
+    })
+}
+
+/// Whether a request failed without knowing if Lokalise handled it, because it was sent but there
+/// was no response, or a server error that may have come after the request was handled.
+fn is_unconfirmed(err: &LokaliseError) -> bool {
+    match err {
+        LokaliseError::Timeout { .. } | LokaliseError::ServerError { .. } => true,
+        LokaliseError::Http(err) => is_transient(err) && !err.is_connect(),
+        _ => false,
+    }
+}
+
+/// Whether the request failed in a way that's likely to work when retried, like not being able to
+/// connect, timing out, or the connection being dropped before there was a response.
+fn is_transient(err: &reqwest::Error) -> bool {
+    if err.is_connect() || err.is_timeout() {
+        return true;
+    }
+
+    let mut source = std::error::Error::source(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<io::Error>() {
+            return matches!(
+                err.kind(),
+                io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe
+            );
+        }
+        source = err.source();
+    }
+    false
+}
+
+fn backoff(retries: u32) -> Duration {
+    const MAX_BACKOFF_SECS: u64 = 32;
+    Duration::from_secs(2u64.saturating_pow(retries).min(MAX_BACKOFF_SECS))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Project {
+    #[serde(rename = "project_id")]
+    pub id: String,
+    pub name: String,
+    /// Not always included, so `--language` has to be given if it's missing
+    #[serde(default)]
+    pub base_language_iso: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Language {
+    pub lang_iso: String,
+    pub lang_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeysResponse {
+    keys: Vec<KeyResponse>,
+    /// Keys in the request that Lokalise rejected
+    #[serde(default)]
+    errors: Vec<KeyError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyError {
+    message: String,
+    #[serde(default)]
+    key: Option<KeyErrorKey>,
+}
+
+/// Which key a `KeyError` is about. Keys being created are identified by name, and keys being
+/// updated by id.
+#[derive(Debug, Deserialize)]
+struct KeyErrorKey {
+    #[serde(default)]
+    key_name: Option<KeyErrorName>,
+    #[serde(default)]
+    key_id: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum KeyErrorName {
+    Plain(String),
+    PerPlatform(BTreeMap<String, String>),
+}
+
+impl KeyErrorName {
+    /// The iOS name, which is what created keys are matched on.
+    fn name(&self) -> &str {
+        match self {
+            KeyErrorName::Plain(name) => name,
+            KeyErrorName::PerPlatform(names) => names
+                .get("ios")
+                .or_else(|| names.values().next())
+                .map_or("", |name| name),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyResponse {
+    key_id: u64,
+    key_name: KeyName,
+    #[serde(default)]
+    tags: Vec<String>,
+    // The rest is only used when exporting keys
+    #[serde(default)]
+    platforms: Vec<Platform>,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    char_limit: u32,
+    #[serde(default)]
+    is_plural: bool,
+    #[serde(default)]
+    plural_name: String,
+    #[serde(default)]
+    is_hidden: bool,
+    #[serde(default)]
+    is_archived: bool,
+    /// Only included when asked for
+    #[serde(default)]
+    translations: Vec<TranslationResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TranslationResponse {
+    language_iso: String,
+    /// The plural forms of plural keys are JSON encoded in this
+    translation: String,
+}
+
+/// Turn a key fetched from Lokalise into the shape it has in input files.
+fn exported_key(key: KeyResponse, language_iso: &str) -> KeyToAdd {
+    let KeyName {
+        ios,
+        android,
+        web,
+        other,
+        custom,
+    } = key.key_name;
+    let differs = |name: String| if name == ios { None } else { Some(name) };
+    let platform_names = PlatformNames {
+        ios: None,
+        android: differs(android),
+        web: differs(web),
+        other: differs(other),
+        custom: custom
+            .into_iter()
+            .filter(|(_, name)| *name != ios)
+            .collect(),
+    };
+    let platform_names = if platform_names.android.is_none()
+        && platform_names.web.is_none()
+        && platform_names.other.is_none()
+        && platform_names.custom.is_empty()
+    {
+        None
+    } else {
+        Some(platform_names)
+    };
+
+    let is_plural = key.is_plural;
+    // The forms are JSON encoded, or empty if the key hasn't been translated yet. Plural keys get
+    // plural translations either way so the file can be added again
+    let plural_forms = |text: String, language_iso: &str| {
+        serde_json::from_str::<PluralForms>(&text)
+            .unwrap_or_else(|_| PluralForms::new(text.clone(), text).for_language(language_iso))
+    };
+    let mut translation = None;
+    let mut languages = BTreeMap::new();
+    for response in key.translations {
+        if response.language_iso == language_iso {
+            translation = Some(if is_plural {
+                Translation::Plural(plural_forms(response.translation, language_iso))
+            } else {
+                Translation::Singular(response.translation)
+            });
+        } else if !response.translation.trim().is_empty() {
+            let text = if is_plural {
+                LanguageTranslation::Plural(plural_forms(
+                    response.translation,
+                    &response.language_iso,
+                ))
+            } else {
+                LanguageTranslation::Singular(response.translation)
+            };
+            languages.insert(response.language_iso, text);
+        }
+    }
+    let translation = translation.unwrap_or_else(|| {
+        if is_plural {
+            Translation::Plural(plural_forms(String::new(), language_iso))
+        } else {
+            Translation::Singular(String::new())
+        }
+    });
+
+    let platforms = key.platforms;
+    // Keys on exactly the built-in platforms get them by default when they're added again
+    let all_platforms = platforms.len() == Platform::all().len()
+        && Platform::all()
+            .iter()
+            .all(|platform| platforms.contains(platform));
+
+    KeyToAdd {
+        key: ios,
+        original_key: None,
+        platform_names,
+        translation,
+        languages,
+        tags: key.tags,
+        platforms: if all_platforms { None } else { Some(platforms) },
+        default_platforms: false,
+        description: Some(key.description).filter(|description| !description.is_empty()),
+        char_limit: NonZeroU32::new(key.char_limit),
+        plural_name: Some(key.plural_name).filter(|name| !name.is_empty()),
+        comments: vec![],
+        screenshot: None,
+        hidden: key.is_hidden,
+        archived: key.is_archived,
+    }
+}
+
+/// The tags used by any of the keys in a project.
+pub fn project_tags(keys: &HashMap<String, ExistingKey>) -> BTreeSet<String> {
+    keys.values()
+        .flat_map(|key| key.tags.iter().cloned())
+        .collect()
+}
+
+/// A key that's already in the project.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExistingKey {
+    pub id: u64,
+    pub tags: Vec<String>,
+}
+
+/// The names of a key on each platform.
+#[derive(Debug, Deserialize)]
+struct KeyName {
+    ios: String,
+    android: String,
+    web: String,
+    other: String,
+    /// Names on the project's custom platforms, keyed by platform
+    #[serde(flatten)]
+    custom: BTreeMap<String, String>,
+}
+
+impl KeyName {
+    fn into_names(self) -> Vec<(Platform, String)> {
+        let mut names = vec![
+            (Platform::Ios, self.ios),
+            (Platform::Android, self.android),
+            (Platform::Web, self.web),
+            (Platform::Other, self.other),
+        ];
+        names.extend(
+            self.custom
+                .into_iter()
+                .map(|(platform, name)| (Platform::Custom(platform), name)),
+        );
+        names
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorResponseInner,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponseInner {
+    code: u32,
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(yaml: &str) -> KeyToAdd {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn update_payload_only_sends_platforms_given_in_the_input() {
+        let mut defaulted = key("key: title\ntranslation: Title");
+        defaulted.platforms = Some(Platform::all());
+        defaulted.default_platforms = true;
+        let explicit = key("key: body\ntranslation: Body\nplatforms: [web]");
+
+        let payload = update_payload("en", &[(1, defaulted), (2, explicit)]).unwrap();
+
+        assert_eq!(payload["keys"][0]["key_id"], json!(1));
+        assert!(payload["keys"][0].get("platforms").is_none());
+        assert_eq!(payload["keys"][1]["platforms"], json!(["web"]));
+    }
+
+    #[test]
+    fn unread_translation_files_are_an_error() {
+        let keys = [key("key: terms\ntranslation_file: terms.md")];
+
+        let err = create_payload("en", &keys).unwrap_err();
+
+        assert!(matches!(err, LokaliseError::UnreadTranslationFile { .. }));
+        assert!(err.to_string().contains("terms.md"), "{}", err);
+    }
+
+    #[test]
+    fn platform_names_are_sent_as_an_object() {
+        let keys = [
+            key("key: title\ntranslation: Title\nplatform_names: { android: title_android }"),
+            key("key: body\ntranslation: Body"),
+        ];
+
+        let payload = create_payload("en", &keys).unwrap();
+
+        assert_eq!(
+            payload["keys"][0]["key_name"],
+            json!({
+                "ios": "title",
+                "android": "title_android",
+                "web": "title",
+                "other": "title",
+            })
+        );
+        assert_eq!(payload["keys"][1]["key_name"], json!("body"));
+    }
+
+    fn key_response(json: serde_json::Value) -> KeyResponse {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn untranslated_plural_keys_are_exported_as_plural() {
+        let key = key_response(json!({
+            "key_id": 1,
+            "key_name": { "ios": "apples", "android": "apples", "web": "apples", "other": "apples" },
+            "platforms": ["ios", "android", "web", "other"],
+            "is_plural": true,
+            "translations": [
+                { "language_iso": "en", "translation": "" },
+                { "language_iso": "fr", "translation": r#"{"one":"Une pomme","other":"Des pommes"}"# },
+                { "language_iso": "de", "translation": "" },
+            ],
+        }));
+
+        let exported = exported_key(key, "en");
+
+        assert!(exported.translation.is_plural());
+        assert_eq!(
+            exported
+                .translation
+                .plural_forms()
+                .unwrap()
+                .categories()
+                .collect::<Vec<_>>(),
+            ["one", "other"]
+        );
+        assert_eq!(exported.languages.len(), 1);
+        assert!(exported.languages["fr"].is_plural());
+        assert_eq!(exported.platforms, None);
+    }
+
+    #[test]
+    fn keys_without_tags_or_other_languages_are_exported_without_them() {
+        let key = key_response(json!({
+            "key_id": 1,
+            "key_name": { "ios": "title", "android": "title", "web": "title", "other": "title" },
+            "platforms": ["web"],
+            "translations": [{ "language_iso": "en", "translation": "Title" }],
+        }));
+
+        let yaml = serde_yaml::to_string(&exported_key(key, "en")).unwrap();
+
+        assert_eq!(
+            yaml,
+            "---\nkey: title\ntranslation: Title\nplatforms:\n  - web"
+        );
+    }
+
+    #[test]
+    fn create_payload_sends_default_platforms() {
+        let mut defaulted = key("key: title\ntranslation: Title");
+        defaulted.platforms = Some(Platform::all());
+        defaulted.default_platforms = true;
+
+        let payload = create_payload("en", &[defaulted]).unwrap();
+
+        assert_eq!(
+            payload["keys"][0]["platforms"],
+            json!(["ios", "android", "web", "other"])
+        );
+    }
+
+    #[test]
+    fn custom_platforms_are_sent_with_their_names() {
+        let keys = [key(
+            "key: title\ntranslation: Title\nplatforms: [web, watch]\nplatform_names: { watch: title_watch }",
+        )];
+
+        let payload = create_payload("en", &keys).unwrap();
+
+        assert_eq!(payload["keys"][0]["platforms"], json!(["web", "watch"]));
+        assert_eq!(
+            payload["keys"][0]["key_name"],
+            json!({
+                "ios": "title",
+                "android": "title",
+                "web": "title",
+                "other": "title",
+                "watch": "title_watch",
+            })
+        );
+    }
+
+    #[test]
+    fn keys_are_found_under_their_custom_platform_names() {
+        let keys = || {
+            vec![key_response(json!({
+                "key_id": 1,
+                "key_name": { "ios": "title", "android": "title", "web": "title", "other": "title", "watch": "title_watch" },
+                "platforms": ["watch"],
+            }))]
+        };
+
+        let mut all = HashMap::new();
+        insert_key_names(&mut all, keys(), None);
+        let mut watch = HashMap::new();
+        let platform = Platform::Custom("watch".to_string());
+        insert_key_names(&mut watch, keys(), Some(&platform));
+
+        assert_eq!(all["title_watch"].id, 1);
+        assert_eq!(all["title"].id, 1);
+        assert_eq!(watch.keys().collect::<Vec<_>>(), ["title_watch"]);
+    }
+
+    #[test]
+    fn custom_platforms_and_names_are_exported() {
+        let key = key_response(json!({
+            "key_id": 1,
+            "key_name": { "ios": "title", "android": "title", "web": "title", "other": "title", "watch": "title_watch" },
+            "platforms": ["ios", "android", "web", "other", "watch"],
+            "translations": [{ "language_iso": "en", "translation": "Title" }],
+        }));
+
+        let exported = exported_key(key, "en");
+
+        assert_eq!(
+            exported.platforms,
+            Some(vec![
+                Platform::Ios,
+                Platform::Android,
+                Platform::Web,
+                Platform::Other,
+                Platform::Custom("watch".to_string()),
+            ])
+        );
+        let names = exported.platform_names.unwrap();
+        assert_eq!(names.custom["watch"], "title_watch");
+        assert_eq!(names.android, None);
+    }
+
+    #[test]
+    fn translations_in_every_language_are_sent() {
+        let key = key("
+            key: title
+            translation: Title
+            languages:
+              fr: Titre
+              de: Titel
+            ");
+
+        let payload = key_payload("en", &key).unwrap();
+
+        assert_eq!(
+            payload["translations"],
+            json!([
+                { "language_iso": "en", "translation": "Title" },
+                { "language_iso": "de", "translation": "Titel" },
+                { "language_iso": "fr", "translation": "Titre" },
+            ])
+        );
+    }
+
+    #[test]
+    fn existing_keys_are_recorded_under_every_platform_name() {
+        let keys = || {
+            vec![
+                key_response(json!({
+                    "key_id": 1,
+                    "key_name": { "ios": "title", "android": "title_android", "web": "titleWeb", "other": "title" },
+                    "tags": ["onboarding"],
+                })),
+                key_response(json!({
+                    "key_id": 2,
+                    "key_name": { "ios": "body", "android": "body", "web": "body", "other": "body" },
+                })),
+            ]
+        };
+
+        let mut all = HashMap::new();
+        insert_key_names(&mut all, keys(), None);
+        let mut names = all.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names, ["body", "title", "titleWeb", "title_android"]);
+        assert_eq!(all["titleWeb"].id, 1);
+        assert_eq!(all["title_android"].tags, ["onboarding"]);
+
+        let mut web = HashMap::new();
+        insert_key_names(&mut web, keys(), Some(&Platform::Web));
+        let mut names = web.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names, ["body", "titleWeb"]);
+    }
+
+    fn plural_forms(json: serde_json::Value) -> Result<PluralForms, serde_json::Error> {
+        serde_json::from_value(json)
+    }
+
+    #[test]
+    fn plural_forms_take_cldr_categories_and_aliases() {
+        let forms = plural_forms(json!({
+            "zero": "No apples",
+            "one": "One apple",
+            "few": "A few apples",
+            "many": "Many apples",
+            "other": "Some apples",
+        }))
+        .unwrap();
+        assert_eq!(
+            forms.categories().collect::<Vec<_>>(),
+            ["few", "many", "one", "other", "zero"]
+        );
+
+        let forms = plural_forms(json!({ "singular": "One apple", "plural": "Apples" })).unwrap();
+        assert_eq!(
+            serde_json::to_value(&forms).unwrap(),
+            json!({ "one": "One apple", "other": "Apples" })
+        );
+    }
+
+    #[test]
+    fn plural_forms_need_known_categories_and_other() {
+        let err = plural_forms(json!({ "one": "One apple", "lots": "Apples" })).unwrap_err();
+        assert!(
+            err.to_string().contains("unknown plural category `lots`"),
+            "{}",
+            err
+        );
+
+        let err = plural_forms(json!({ "one": "One apple" })).unwrap_err();
+        assert!(err.to_string().contains("must include `other`"), "{}", err);
+
+        let err =
+            plural_forms(json!({ "one": "One apple", "singular": "An apple", "other": "Apples" }))
+                .unwrap_err();
+        assert!(err.to_string().contains("`one` is given twice"), "{}", err);
+    }
+
+    #[test]
+    fn descriptions_are_only_sent_when_given() {
+        let keys = [
+            key("key: title\ntranslation: Title\ndescription: Shown at the top"),
+            key("key: body\ntranslation: Body"),
+        ];
+
+        let payload = create_payload("en", &keys).unwrap();
+
+        assert_eq!(payload["keys"][0]["description"], json!("Shown at the top"));
+        assert!(payload["keys"][1].get("description").is_none());
+    }
+
+    #[test]
+    fn unexpected_responses_include_the_start_of_the_body() {
+        let html = format!("<html>{}</html>", "a".repeat(300));
+        let err = unexpected_response(StatusCode::BAD_GATEWAY, &html);
+        match &err {
+            LokaliseError::Parse { status, snippet } => {
+                assert_eq!(*status, StatusCode::BAD_GATEWAY);
+                assert_eq!(snippet.chars().count(), 201);
+                assert!(snippet.starts_with("<html>aaa"));
+                assert!(snippet.ends_with('…'));
+            }
+            err => panic!("{:?}", err),
+        }
+
+        let err = unexpected_response(StatusCode::OK, " \n");
+        assert_eq!(
+            err.to_string(),
+            "Failed to parse lokalise response\nGot 200 OK with body: <empty body>"
+        );
+    }
+
+    #[test]
+    fn char_limits_are_only_sent_when_given() {
+        let keys = [
+            key("key: title\ntranslation: Title\nchar_limit: 40"),
+            key("key: body\ntranslation: Body"),
+        ];
+
+        let payload = create_payload("en", &keys).unwrap();
+
+        assert_eq!(payload["keys"][0]["char_limit"], json!(40));
+        assert!(payload["keys"][1].get("char_limit").is_none());
+        assert!(serde_yaml::from_str::<KeyToAdd>("key: a\ntranslation: A\nchar_limit: 0").is_err());
+    }
+
+    #[test]
+    fn hidden_and_archived_keys_are_marked_as_such() {
+        let keys = [
+            key("key: title\ntranslation: Title\nhidden: true\narchived: true"),
+            key("key: body\ntranslation: Body"),
+        ];
+
+        let payload = create_payload("en", &keys).unwrap();
+
+        assert_eq!(payload["keys"][0]["is_hidden"], json!(true));
+        assert_eq!(payload["keys"][0]["is_archived"], json!(true));
+        assert!(!keys[1].hidden && !keys[1].archived);
+        assert!(payload["keys"][1].get("is_hidden").is_none());
+        assert!(payload["keys"][1].get("is_archived").is_none());
+    }
+
+    #[tokio::test]
+    async fn translation_files_are_read_next_to_the_input() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("texts")).unwrap();
+        std::fs::write(dir.path().join("texts/terms.md"), "# Terms\n\nBe nice.\n").unwrap();
+        let data = || -> Data {
+            serde_yaml::from_str(
+                "
+                keys:
+                  - key: terms
+                    translation_file: texts/terms.md
+                  - key: title
+                    translation: Title
+                ",
+            )
+            .unwrap()
+        };
+
+        let mut found = data();
+        found.resolve_files(dir.path()).await.unwrap();
+        assert!(matches!(
+            &found.keys[0].translation,
+            Translation::Singular(text) if text == "# Terms\n\nBe nice.\n"
+        ));
+        assert!(
+            matches!(&found.keys[1].translation, Translation::Singular(text) if text == "Title")
+        );
+
+        let mut missing = data();
+        let err = missing
+            .resolve_files(&dir.path().join("elsewhere"))
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Failed to read the translation of `terms` from "),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn summaries_are_sorted_in_input_order() {
+        let created = |key: &str| KeyWithId {
+            key: key.to_string(),
+            key_id: 1,
+        };
+        let failed = |key: &str| FailedKey {
+            key: key.to_string(),
+            reason: None,
+        };
+        let mut summary = Summary {
+            created: vec![created("c"), created("a"), created("unknown"), created("b")],
+            skipped: vec!["e".to_string(), "d".to_string()],
+            failed: vec![failed("g"), failed("f")],
+            ..Summary::default()
+        };
+        let input_order = ["a", "b", "c", "d", "e", "f", "g"]
+            .iter()
+            .enumerate()
+            .map(|(idx, key)| (key.to_string(), idx))
+            .collect();
+
+        summary.sort(&input_order);
+
+        let created = summary
+            .created
+            .iter()
+            .map(|key| key.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(created, ["a", "b", "c", "unknown"]);
+        assert_eq!(summary.skipped, ["d", "e"]);
+        let failed = summary
+            .failed
+            .iter()
+            .map(|key| key.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(failed, ["f", "g"]);
+    }
+
+    #[test]
+    fn comments_are_only_sent_when_creating_keys() {
+        let commented =
+            || key("key: title\ntranslation: Title\ncomments: [See the ticket, Keep it short]");
+        let keys = [commented(), key("key: body\ntranslation: Body")];
+
+        let payload = create_payload("en", &keys).unwrap();
+        assert_eq!(
+            payload["keys"][0]["comments"],
+            json!([{ "comment": "See the ticket" }, { "comment": "Keep it short" }])
+        );
+        assert!(payload["keys"][1].get("comments").is_none());
+
+        // Updating would add them again every time
+        let payload = update_payload("en", &[(1, commented())]).unwrap();
+        assert!(payload["keys"][0].get("comments").is_none());
+    }
+
+    #[test]
+    fn projects_without_a_base_language_are_parsed() {
+        let project: Project =
+            serde_json::from_value(json!({ "project_id": "123.abc", "name": "App" })).unwrap();
+        assert_eq!(project.base_language_iso, None);
+
+        let project: Project = serde_json::from_value(json!({
+            "project_id": "123.abc",
+            "name": "App",
+            "base_language_iso": null,
+        }))
+        .unwrap();
+        assert_eq!(project.base_language_iso, None);
+    }
+
+    fn api_error(code: u32, message: &str) -> LokaliseError {
+        ErrorResponseInner {
+            code,
+            message: message.to_string(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn error_responses_become_matching_errors() {
+        assert!(matches!(
+            api_error(401, "Invalid token"),
+            LokaliseError::Unauthorized
+        ));
+        assert!(matches!(
+            api_error(400, "Unauthorized"),
+            LokaliseError::Unauthorized
+        ));
+        assert!(matches!(
+            api_error(404, "Project not found"),
+            LokaliseError::NotFound { message } if message == "Project not found"
+        ));
+        let err = api_error(400, "Invalid `key_name`");
+        assert!(
+            matches!(&err, LokaliseError::Api { code: 400, message } if message == "Invalid `key_name`")
+        );
+        assert_eq!(
+            err.to_string(),
+            "Lokalise request failed\nGot 400 Invalid `key_name`"
+        );
+    }
+
+    #[test]
+    fn tags_can_be_a_list_or_a_string() {
+        let tags =
+            |tags: &str| key(&format!("key: title\ntranslation: Title\ntags: {}", tags)).tags;
+
+        assert_eq!(tags("[onboarding, home]"), ["onboarding", "home"]);
+        assert_eq!(tags("onboarding"), ["onboarding"]);
+        assert_eq!(
+            tags("\"onboarding, home; release_42 ,\""),
+            ["onboarding", "home", "release_42"]
+        );
+        assert!(key("key: title\ntranslation: Title").tags.is_empty());
+
+        let err = serde_yaml::from_str::<KeyToAdd>("key: title\ntranslation: Title\ntags: 42")
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("a list of tags or a string of tags"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn plural_names_are_sent_for_plural_keys() {
+        let keys = [
+            key("key: apples\ntranslations: { one: One apple, other: Apples }\nplural_name: count"),
+            key("key: pears\ntranslations: { one: One pear, other: Pears }"),
+            key("key: title\ntranslation: Title"),
+        ];
+
+        let payload = create_payload("en", &keys).unwrap();
+
+        assert_eq!(payload["keys"][0]["plural_name"], json!("count"));
+        assert_eq!(payload["keys"][0]["is_plural"], json!(true));
+        assert!(payload["keys"][1].get("plural_name").is_none());
+        assert!(payload["keys"][2].get("plural_name").is_none());
+        assert_eq!(payload["keys"][2]["is_plural"], json!(false));
+    }
+
+    #[test]
+    fn create_payload_for_singular_and_plural_keys() {
+        let data: Data = serde_yaml::from_str(
+            "
+            keys:
+              - key: title
+                translation: Title
+                tags: [onboarding]
+                platforms: [ios, web]
+              - key: apples
+                translations: { singular: One apple, plural: '{count} apples' }
+                languages:
+                  fr: { one: Une pomme, other: '{count} pommes' }
+            ",
+        )
+        .unwrap();
+
+        let payload = create_payload("en", &data.keys).unwrap();
+
+        assert_eq!(
+            payload,
+            json!({
+                "keys": [
+                    {
+                        "key_name": "title",
+                        "translations": [{ "language_iso": "en", "translation": "Title" }],
+                        "is_plural": false,
+                        "platforms": ["ios", "web"],
+                        "tags": ["onboarding"],
+                    },
+                    {
+                        "key_name": "apples",
+                        "translations": [
+                            {
+                                "language_iso": "en",
+                                "translation": { "one": "One apple", "other": "{count} apples" },
+                            },
+                            {
+                                "language_iso": "fr",
+                                "translation": { "one": "Une pomme", "other": "{count} pommes" },
+                            },
+                        ],
+                        "is_plural": true,
+                        "tags": [],
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn defaults_fill_in_what_keys_dont_have() {
+        let mut data = serde_yaml::from_str::<Data>(
+            "
+            defaults:
+              tags: [onboarding]
+              platforms: [web]
+              description: On the welcome screen
+              char_limit: 40
+            keys:
+              - key: title
+                translation: Title
+              - key: body
+                translation: Body
+                tags: [legal]
+                platforms: [ios]
+                description: Below the title
+                char_limit: 200
+            ",
+        )
+        .unwrap();
+        let defaults = data.defaults.take().unwrap();
+
+        for key in &mut data.keys {
+            key.apply_defaults(&defaults);
+        }
+
+        let title = &data.keys[0];
+        assert_eq!(title.tags, ["onboarding"]);
+        assert_eq!(title.platforms, Some(vec![Platform::Web]));
+        assert_eq!(title.description.as_deref(), Some("On the welcome screen"));
+        assert_eq!(title.char_limit.map(NonZeroU32::get), Some(40));
+        let body = &data.keys[1];
+        assert_eq!(body.tags, ["legal"]);
+        assert_eq!(body.platforms, Some(vec![Platform::Ios]));
+        assert_eq!(body.description.as_deref(), Some("Below the title"));
+        assert_eq!(body.char_limit.map(NonZeroU32::get), Some(200));
+    }
+
+    #[test]
+    fn the_json_summary_includes_the_elapsed_time() {
+        let created = |key: &str| KeyWithId {
+            key: key.to_string(),
+            key_id: 1,
+        };
+        let mut summary = Summary {
+            created: vec![created("title"), created("body")],
+            ..Summary::default()
+        };
+        assert!(summary.to_json().get("elapsed_ms").is_none());
+
+        summary.elapsed = Some(Duration::from_millis(500));
+
+        let json = summary.to_json();
+        assert_eq!(json["elapsed_ms"], json!(500));
+        assert_eq!(json["keys_per_second"], json!(4.0));
+    }
+}
@@ -1,297 +1,3155 @@
 use anyhow::{Error, Result};
-use reqwest::{
-    header::{HeaderMap, HeaderValue},
-    Client,
+use futures::{stream, StreamExt, TryStreamExt};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, LevelFilter};
+use lokalise_bulk_add_keys::{
+    create_payload, plural_categories, project_tags, update_payload, ApiToken, BatchOptions,
+    ClientConfig, Data, DeleteSummary, ExistingKey, FailedKey, KeyToAdd, Language,
+    LanguageTranslation, LokaliseClient, LokaliseError, Markers, Platform, PluralForms, Project,
+    ProxyConfig, Rename, RenameSummary, Summary, Translation,
 };
-use serde::Deserialize;
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
-use std::{collections::HashSet, env, path::PathBuf};
-use structopt::StructOpt;
-use tokio::fs;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    env,
+    future::Future,
+    io,
+    num::{NonZeroU32, NonZeroUsize},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime},
+};
+use structopt::{clap::AppSettings, StructOpt};
+use tokio::{fs, io::AsyncReadExt};
 
 #[derive(Debug, StructOpt)]
+#[structopt(
+    setting = AppSettings::SubcommandsNegateReqs,
+    setting = AppSettings::DisableVersion,
+    after_help = "EXIT CODES:
+    0    All keys were created, updated, or skipped
+    1    The options or the input file are invalid
+    2    Some keys or their screenshots couldn't be created, updated, or deleted
+    3    Lokalise couldn't be reached or responded with an error
+    4    With `--diff-only`, some keys in the input aren't in Lokalise yet"
+)]
 struct Opt {
-    /// Name of the project in Lokalise
-    #[structopt(short = "p", long = "project")]
-    project: String,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+
+    /// Print the version, git commit, and target this was built for. As JSON with `--output json`
+    #[structopt(short = "V", long = "version")]
+    version: bool,
+
+    /// Name of the project in Lokalise. Defaults to the `LOKALISE_PROJECT` env var
+    #[structopt(short = "p", long = "project", global = true)]
+    project: Option<String>,
+
+    /// ID of the project in Lokalise. Can be used instead of `--project`
+    #[structopt(long = "project-id", conflicts_with = "project", global = true)]
+    project_id: Option<String>,
+
+    /// Language the `translation` or `translations` of each key are in. Defaults to the
+    /// project's base language
+    #[structopt(short = "l", long = "language")]
+    language: Option<String>,
+
+    /// Treat this as the base language of the project instead of the one configured in Lokalise,
+    /// warning if they differ. The keys' main translations are added to it like with `--language`
+    #[structopt(long = "base-language", conflicts_with = "language")]
+    base_language: Option<String>,
+
+    /// Log the requests made to Lokalise
+    #[structopt(short = "v", long = "verbose", global = true)]
+    verbose: bool,
+
+    /// Only print a one line summary instead of every key
+    #[structopt(short = "q", long = "quiet", conflicts_with = "verbose", global = true)]
+    quiet: bool,
+
+    /// Lokalise API token. Defaults to the `LOKALISE_API_TOKEN` env var
+    #[structopt(long = "token", global = true)]
+    token: Option<ApiToken>,
+
+    /// Read the Lokalise API token from this file. Takes precedence over the config file and the
+    /// env var, but not `--token`
+    #[structopt(
+        long = "token-file",
+        value_name = "PATH",
+        global = true,
+        parse(from_os_str)
+    )]
+    token_file: Option<PathBuf>,
+
+    /// Don't upload things to Lokalise. With `--dry-run` or `--dry-run=local` the input file is
+    /// just parsed. With `--dry-run=remote` the keys are also compared with the ones in the
+    /// project, to show what would be created, updated, or skipped. With `--dry-run=payload` the
+    /// requests that would be sent to create and update the keys are printed as JSON
+    #[structopt(
+        long = "dry-run",
+        require_equals = true,
+        possible_values = &["local", "remote", "payload"]
+    )]
+    dry_run: Option<Option<DryRun>>,
+
+    /// Only check that the input is valid, without contacting Lokalise. The plural categories
+    /// of the main translations are checked too if `--language` is given
+    #[structopt(long = "check", conflicts_with = "dry-run")]
+    check: bool,
+
+    /// Compare the keys with the ones in the project like `--dry-run=remote`, and exit with code
+    /// 4 if any of them aren't in Lokalise yet. Meant for failing CI when the input has drifted
+    #[structopt(long = "diff-only", conflicts_with_all = &["dry-run", "check"])]
+    diff_only: bool,
+
+    /// Treat warnings as errors, like keys with empty translations or unknown tags, and stop
+    /// before uploading anything
+    #[structopt(long = "strict")]
+    strict: bool,
+
+    /// Warn about tags that none of the keys in the project have yet, to catch typos
+    #[structopt(long = "warn-unknown-tags")]
+    warn_unknown_tags: bool,
+
+    /// Warn if the project has a key whose name only differs from an incoming key's in casing,
+    /// like `Title` and `title`. Also checked with `--dry-run=remote`
+    #[structopt(long = "check-case-collisions")]
+    check_case_collisions: bool,
+
+    /// With `--dry-run`, only show the keys that have this tag
+    #[structopt(long = "filter-tag", requires = "dry-run")]
+    filter_tag: Option<String>,
+
+    /// Prepended to the name of every key, like `onboarding.` to turn `title` into
+    /// `onboarding.title`
+    #[structopt(long = "key-prefix", global = true)]
+    key_prefix: Option<String>,
+
+    /// Only add the first N keys, after sorting them with `--sort`
+    #[structopt(long = "limit", value_name = "N")]
+    limit: Option<usize>,
+
+    /// Lowercase the name of every key, after adding `--key-prefix`
+    #[structopt(long = "lowercase-keys", global = true)]
+    lowercase_keys: bool,
+
+    /// Add the main translation of each key to the project's other languages that it doesn't have
+    /// a translation for. Only keys being created get them, updated keys keep the translations
+    /// they have
+    #[structopt(long = "fill-missing-languages")]
+    fill_missing_languages: bool,
+
+    /// Don't send any platforms for keys that don't list their own, instead of all of them
+    #[structopt(long = "no-platforms")]
+    no_platforms: bool,
+
+    /// Sort the keys by name before doing anything with them
+    #[structopt(long = "sort")]
+    sort: bool,
+
+    /// Add this tag to every key. Can be given more than once
+    #[structopt(long = "tag", number_of_values = 1)]
+    tags: Vec<String>,
+
+    /// Update keys that already exist instead of failing. New keys are still created, so the
+    /// same file can be uploaded again and again. Also available as `--upsert`
+    #[structopt(long = "update", alias = "upsert")]
+    update: bool,
+
+    /// When updating keys, add the new tags to the existing ones instead of replacing them
+    #[structopt(long = "tag-merge", requires = "update")]
+    tag_merge: bool,
+
+    /// Only look at this platform's key names when checking if keys already exist. By default a
+    /// key exists if any platform has a key with its name. Either ios, android, web, other, or
+    /// one of the `custom_platforms` in the config file
+    #[structopt(long = "match-platform", value_name = "PLATFORM")]
+    match_platform: Option<Platform>,
+
+    /// Skip keys that already exist instead of failing
+    #[structopt(long = "skip-existing", conflicts_with = "update")]
+    skip_existing: bool,
+
+    /// Delete keys that already exist and create them again, dropping their current
+    /// translations and tags. Asks for confirmation before deleting anything
+    #[structopt(long = "replace", conflicts_with_all = &["update", "skip-existing"])]
+    replace: bool,
+
+    /// Ask for confirmation before creating more than this many keys. Can also be set in the
+    /// config file [default: 100]
+    #[structopt(long = "confirm-threshold", value_name = "N")]
+    confirm_threshold: Option<usize>,
+
+    /// Don't ask for confirmation before deleting the keys to replace, or creating more than
+    /// `--confirm-threshold` keys
+    #[structopt(short = "y", long = "yes")]
+    yes: bool,
 
-    /// Don't upload things to Lokalise, just parse the input file
-    #[structopt(long = "dry-run")]
-    dry_run: bool,
+    /// How many keys to send to Lokalise per request [default: 500]
+    #[structopt(long = "chunk-size")]
+    chunk_size: Option<NonZeroUsize>,
 
-    /// Input file containing the keys you want to add
+    /// How many requests creating or updating keys to send at the same time
+    #[structopt(long = "concurrency", default_value = "4")]
+    concurrency: NonZeroUsize,
+
+    /// How many pages of existing keys to fetch from Lokalise at the same time
+    #[structopt(long = "page-concurrency", default_value = "8")]
+    page_concurrency: NonZeroUsize,
+
+    /// How many idle connections to Lokalise to keep open for reuse. Defaults to the larger of
+    /// `--concurrency` and `--page-concurrency`, so every request sent at the same time can reuse
+    /// a connection. Lower values open fewer connections but may mean reconnecting more often
+    #[structopt(long = "max-connections")]
+    max_connections: Option<NonZeroUsize>,
+
+    /// Base URL of the Lokalise API. Defaults to the `LOKALISE_API_BASE_URL` env var, or
+    /// https://api.lokalise.com/api2
+    #[structopt(long = "api-base-url", global = true)]
+    api_base_url: Option<String>,
+
+    /// How many seconds to wait for a response from Lokalise before giving up
+    #[structopt(long = "timeout", default_value = "30", global = true)]
+    timeout: u64,
+
+    /// Send requests through this proxy. Takes precedence over the `HTTPS_PROXY` and `HTTP_PROXY`
+    /// env vars
+    #[structopt(long = "proxy", global = true)]
+    proxy: Option<String>,
+
+    /// Don't use a proxy, even if `HTTPS_PROXY` or `HTTP_PROXY` is set
+    #[structopt(long = "no-proxy", conflicts_with = "proxy", global = true)]
+    no_proxy: bool,
+
+    /// Extra header to send with every request, like `X-Routing-Token:abc`. Can be given
+    /// multiple times
+    #[structopt(
+        long = "header",
+        value_name = "KEY:VALUE",
+        number_of_values = 1,
+        global = true
+    )]
+    headers: Vec<Header>,
+
+    /// How many times to retry a request that was rate limited by Lokalise, got a server error,
+    /// failed to connect, or timed out. Keys that were created by a request that failed anyway
+    /// aren't sent again, and screenshot uploads are only retried if they weren't sent
+    #[structopt(long = "max-retries", default_value = "3", global = true)]
+    max_retries: u32,
+
+    /// Save the projects and existing keys fetched from Lokalise in this directory, and use them
+    /// instead of fetching them again for `--cache-ttl` seconds
+    #[structopt(long = "cache-dir", parse(from_os_str), global = true)]
+    cache_dir: Option<PathBuf>,
+
+    /// How many seconds the projects and keys saved in `--cache-dir` are used for
+    #[structopt(long = "cache-ttl", default_value = "300", global = true)]
+    cache_ttl: u64,
+
+    /// Fetch the projects and keys again even if they're in `--cache-dir`
+    #[structopt(long = "no-cache", global = true)]
+    no_cache: bool,
+
+    /// Upload the `screenshot` of each key after it's created
+    #[structopt(long = "with-screenshots")]
+    with_screenshots: bool,
+
+    /// Fetch the keys of the project again after creating keys, and fail if any of them are
+    /// missing
+    #[structopt(long = "verify")]
+    verify: bool,
+
+    /// Stop sending keys to Lokalise as soon as a chunk has keys that failed, instead of sending
+    /// all of them and reporting the failures at the end
+    #[structopt(long = "fail-fast")]
+    fail_fast: bool,
+
+    /// How to print the results
+    #[structopt(
+        long = "output",
+        default_value = "text",
+        possible_values = &["text", "json"],
+        global = true
+    )]
+    output: OutputFormat,
+
+    /// Print plain markers like `[OK]` and `[FAIL]` in front of the keys instead of emoji
+    #[structopt(long = "ascii", global = true)]
+    ascii: bool,
+
+    /// Don't show a progress bar while keys are being created and updated. It's also hidden when
+    /// stdout isn't a terminal, with `--quiet`, or with `--output json`
+    #[structopt(long = "no-progress")]
+    no_progress: bool,
+
+    /// Write what was created, updated, skipped, and failed to this file. YAML if the extension
+    /// is `.yaml` or `.yml`, JSON otherwise
+    #[structopt(long = "report", parse(from_os_str))]
+    report: Option<PathBuf>,
+
+    /// Format of the input file. Detected from the file extension if not given
+    #[structopt(long = "format", possible_values = &["yaml", "json", "csv"])]
+    format: Option<InputFormat>,
+
+    /// The keys to add as YAML or JSON, in the same shape as the input files. Can be used instead
+    /// of `FILE`
+    #[structopt(long = "keys", value_name = "YAML", conflicts_with = "FILE")]
+    inline_keys: Option<String>,
+
+    /// Input files containing the keys you want to add. Either YAML, JSON, or CSV. Glob patterns
+    /// like `keys/*.yaml` are expanded. Use `-` to read from stdin
     #[structopt(name = "FILE", parse(from_os_str))]
-    input: PathBuf,
+    inputs: Vec<PathBuf>,
+
+    /// Names used for plural forms in the input files, and the CLDR categories they stand for.
+    /// Only set in the config file
+    #[structopt(skip)]
+    plural_aliases: BTreeMap<String, String>,
+
+    /// The project's platforms besides ios, android, web, and other. Only set in the config file
+    #[structopt(skip)]
+    custom_platforms: Vec<Platform>,
 }
 
-#[tokio::main]
-async fn main() {
-    match try_main().await {
-        Ok(()) => {}
-        Err(err) => {
-            eprintln!("{}", err);
-            std::process::exit(1);
+impl Opt {
+    /// Fill in the options that weren't given on the command line from the config file.
+    fn merge_config(&mut self, config: Config) {
+        if self.token.is_none() && self.token_file.is_none() {
+            self.token = config.token;
+        }
+        if self.project.is_none() && self.project_id.is_none() {
+            self.project = config.project;
+        }
+        if self.api_base_url.is_none() {
+            self.api_base_url = config.api_base_url;
+        }
+        if self.chunk_size.is_none() {
+            self.chunk_size = config.chunk_size;
         }
+        if self.cache_dir.is_none() {
+            self.cache_dir = config.cache_dir;
+        }
+        if self.confirm_threshold.is_none() {
+            self.confirm_threshold = config.confirm_threshold;
+        }
+        self.plural_aliases = config.plural_aliases;
+        self.custom_platforms = config
+            .custom_platforms
+            .into_iter()
+            .map(Platform::from)
+            .collect();
     }
-}
 
-async fn try_main() -> Result<()> {
-    let opt = Opt::from_args();
-
-    let file_contents = fs::read_to_string(&opt.input).await?;
-    let keys_to_add = serde_yaml::from_str::<Data>(&file_contents)?.keys;
+    /// The built-in platforms and the `custom_platforms` from the config file, which keys are on
+    /// unless they list their own.
+    fn platforms(&self) -> Vec<Platform> {
+        let mut platforms = Platform::all();
+        for platform in &self.custom_platforms {
+            if !platforms.contains(platform) {
+                platforms.push(platform.clone());
+            }
+        }
+        platforms
+    }
 
-    if opt.dry_run {
-        println!("{:#?}", keys_to_add);
-        return Ok(());
+    /// Fail for custom platforms that aren't in `custom_platforms`, since they're most likely a
+    /// typo and Lokalise would reject the keys anyway.
+    fn check_platform(&self, platform: &Platform) -> Result<()> {
+        if self.platforms().contains(platform) {
+            return Ok(());
+        }
+        Err(Error::msg(format!(
+            "Unknown platform `{}`, add it to `custom_platforms` in {} if the project has it",
+            platform,
+            Config::FILE_NAME
+        )))
     }
 
-    let lokalise_token = env::var("LOKALISE_API_TOKEN")
-        .map_err(|_| Error::msg("Missing env var LOKALISE_API_TOKEN"))?;
+    fn api_base_url(&self) -> String {
+        self.api_base_url
+            .clone()
+            .or_else(|| env::var("LOKALISE_API_BASE_URL").ok())
+            .unwrap_or_else(|| "https://api.lokalise.com/api2".to_string())
+    }
 
-    let client = LokaliseClient::new(lokalise_token)?;
+    /// The language of the main translations, if it isn't the project's base language.
+    fn language(&self) -> Option<&String> {
+        self.language.as_ref().or(self.base_language.as_ref())
+    }
 
-    let projects = client.projects().await?;
-    let project = projects
-        .into_iter()
-        .find(|p| p.name == opt.project)
-        .ok_or_else(|| Error::msg(format!("No project name '{}' was found", opt.project)))?;
+    fn markers(&self) -> Markers {
+        if self.ascii {
+            Markers::ASCII
+        } else {
+            Markers::EMOJI
+        }
+    }
 
-    let all_keys = client.all_keys(&project).await?;
-    for key in &keys_to_add {
-        if all_keys.contains(&key.key) {
-            return Err(Error::msg(format!("The key `{}` already exists", key.key)));
+    /// The name of the project, falling back to the `LOKALISE_PROJECT` env var if neither it nor
+    /// the project id was given.
+    fn project(&self) -> Option<String> {
+        match (&self.project, &self.project_id) {
+            (Some(project), _) => Some(project.clone()),
+            (None, Some(_)) => None,
+            (None, None) => env::var("LOKALISE_PROJECT").ok(),
         }
     }
 
-    client.create_keys(&project, keys_to_add).await?;
+    fn confirm_threshold(&self) -> usize {
+        self.confirm_threshold.unwrap_or(100)
+    }
 
-    Ok(())
-}
+    fn chunk_size(&self) -> NonZeroUsize {
+        self.chunk_size
+            .unwrap_or_else(|| NonZeroUsize::new(500).unwrap())
+    }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Data {
-    keys: Vec<KeyToAdd>,
+    fn cache(&self) -> Option<Cache> {
+        self.cache_dir.as_ref().map(|dir| Cache {
+            dir: dir.clone(),
+            platforms: self.platforms(),
+            ttl: Duration::from_secs(self.cache_ttl),
+            read: !self.no_cache,
+        })
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct KeyToAdd {
-    key: String,
-    #[serde(flatten)]
-    translation: Translation,
+/// Defaults read from `lokalise.toml`, in the current directory or `$HOME/.config`.
+///
+/// Options given on the command line take precedence over the config file, which takes precedence
+/// over env vars.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    token: Option<ApiToken>,
+    project: Option<String>,
+    api_base_url: Option<String>,
+    chunk_size: Option<NonZeroUsize>,
+    cache_dir: Option<PathBuf>,
+    confirm_threshold: Option<usize>,
     #[serde(default)]
-    tags: Vec<String>,
+    plural_aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    custom_platforms: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-enum Translation {
-    #[serde(rename = "translation")]
-    Singular(String),
-    #[serde(rename = "translations")]
-    Plural { singular: String, plural: String },
+impl Config {
+    const FILE_NAME: &'static str = "lokalise.toml";
+
+    async fn load() -> Result<Option<Config>> {
+        let mut paths = vec![PathBuf::from(Config::FILE_NAME)];
+        if let Some(home) = env::var_os("HOME") {
+            paths.push(PathBuf::from(home).join(".config").join(Config::FILE_NAME));
+        }
+
+        for path in paths {
+            let contents = match fs::read_to_string(&path).await {
+                Ok(contents) => contents,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    return Err(Error::msg(format!(
+                        "Failed to read {}: {}",
+                        path.display(),
+                        err
+                    )))
+                }
+            };
+
+            return toml::from_str(&contents)
+                .map(Some)
+                .map_err(|err| Error::msg(format!("Failed to parse {}: {}", path.display(), err)));
+        }
+
+        Ok(None)
+    }
 }
 
+/// Responses from Lokalise saved in `--cache-dir` as JSON files.
 #[derive(Debug)]
-struct LokaliseClient {
-    client: Client,
+struct Cache {
+    dir: PathBuf,
+    /// The platforms there can be a cache of the keys matched on
+    platforms: Vec<Platform>,
+    ttl: Duration,
+    /// False with `--no-cache`, so everything is fetched again and the cache just updated
+    read: bool,
 }
 
-impl LokaliseClient {
-    fn new(token: String) -> Result<Self> {
-        let mut headers = HeaderMap::new();
-        headers.insert("x-api-token", HeaderValue::from_str(&token)?);
-        let client = Client::builder().default_headers(headers).build()?;
+impl Cache {
+    /// The saved value, unless it's missing, older than the TTL, or can't be parsed.
+    async fn get<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        if !self.read {
+            return None;
+        }
+
+        let path = self.path(name);
+        let modified = fs::metadata(&path).await.ok()?.modified().ok()?;
+        match modified.elapsed() {
+            Ok(age) if age <= self.ttl => {}
+            _ => return None,
+        }
+
+        let contents = fs::read(&path).await.ok()?;
+        match serde_json::from_slice(&contents) {
+            Ok(value) => {
+                debug!("Using cached {}", path.display());
+                Some(value)
+            }
+            Err(err) => {
+                debug!("Ignoring invalid cache file {}: {}", path.display(), err);
+                None
+            }
+        }
+    }
+
+    async fn put<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        let path = self.path(name);
+        let contents = serde_json::to_vec(value)?;
+        let write = async {
+            fs::create_dir_all(&self.dir).await?;
+            fs::write(&path, contents).await
+        };
+        write.await.map_err(|err| {
+            Error::msg(format!(
+                "Failed to write cache file {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    }
+
+    /// Forget the existing keys of the project, since they're about to change.
+    async fn remove_keys(&self, project: &Project) {
+        let platforms = self.platforms.iter().map(Some).chain(Some(None));
+        for platform in platforms {
+            // It not being there is just as good
+            let _ = fs::remove_file(self.path(&Cache::keys_name(project, platform))).await;
+        }
+    }
+
+    fn keys_name(project: &Project, match_platform: Option<&Platform>) -> String {
+        match match_platform {
+            Some(platform) => format!("keys-{}-{}", project.id, platform),
+            None => format!("keys-{}", project.id),
+        }
+    }
 
-        Ok(Self { client })
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
     }
+}
 
-    async fn projects(&self) -> Result<Vec<Project>> {
-        #[derive(Debug, Deserialize)]
-        struct ProjectsResponse {
-            projects: Vec<Project>,
+/// Use the value in the cache if there is one, otherwise fetch it and save it there.
+async fn cached<T, F>(cache: Option<&Cache>, name: &str, fetch: F) -> Result<T>
+where
+    T: Serialize + DeserializeOwned,
+    F: Future<Output = Result<T, LokaliseError>>,
+{
+    if let Some(cache) = cache {
+        if let Some(value) = cache.get(name).await {
+            return Ok(value);
         }
+    }
 
-        let res = self.client.get(&self.url("/projects")).send().await?;
+    let value = fetch.await?;
+    if let Some(cache) = cache {
+        cache.put(name, &value).await?;
+    }
+    Ok(value)
+}
 
-        Ok(res.json::<ProjectsResponse>().await?.projects)
+/// The keys that are already in the project, from the cache if possible.
+///
+/// Given the `names` of only a few keys, and without a cache, just the keys with those names are
+/// fetched instead, which is a lot faster in big projects.
+async fn existing_keys(
+    client: &LokaliseClient,
+    project: &Project,
+    opt: &Opt,
+    names: Option<Vec<&str>>,
+) -> Result<HashMap<String, ExistingKey>> {
+    /// Above this the names make the URL too long, and fetching all keys is no slower anyway.
+    const MAX_NAMES_TO_FILTER: usize = 100;
+
+    match names {
+        Some(names)
+            if names.len() <= MAX_NAMES_TO_FILTER
+                && opt.cache().is_none()
+                && !names.iter().any(|name| name.contains(',')) =>
+        {
+            return Ok(client
+                .keys_named(project, &names, opt.match_platform.as_ref())
+                .await?);
+        }
+        _ => {}
     }
 
-    async fn all_keys(&self, project: &Project) -> Result<HashSet<String>> {
-        let mut key_names = HashSet::new();
-        let limit = 1000;
-        let mut page = 1;
+    cached(
+        opt.cache().as_ref(),
+        &Cache::keys_name(project, opt.match_platform.as_ref()),
+        client.all_keys(project, opt.page_concurrency, opt.match_platform.as_ref()),
+    )
+    .await
+}
 
-        loop {
-            let res = self
-                .client
-                .get(&self.url(&format!("/projects/{}/keys", &project.id)))
-                .query(&[("limit", limit), ("page", page)])
-                .send()
-                .await?;
-            let keys = res.json::<KeysResponse>().await?.keys;
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// List the projects the API token has access to
+    #[structopt(name = "list-projects")]
+    ListProjects,
 
-            let keys_count = keys.len();
+    /// List the languages enabled in the project
+    #[structopt(name = "languages")]
+    Languages,
 
-            for key in keys {
-                let key = key.key_name;
-                if key.ios == key.android && key.android == key.web && key.web == key.other {
-                    key_names.insert(key.ios.clone());
-                } else {
-                    return Err(Error::msg(
-                        "Key with different names per platform isn't supported",
-                    ));
-                }
-            }
+    /// Delete the keys in the input files from the project
+    #[structopt(name = "delete")]
+    Delete {
+        /// Only print which keys would be deleted
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
 
-            if keys_count < limit {
-                break;
-            }
+        /// Don't ask for confirmation before deleting
+        #[structopt(short = "y", long = "yes")]
+        yes: bool,
 
-            page += 1;
+        /// Input files containing the keys to delete, in the same format as when adding keys
+        #[structopt(name = "FILE", parse(from_os_str), required = true)]
+        inputs: Vec<PathBuf>,
+    },
+
+    /// Write the keys in the project to a YAML file that can be edited and added again. JSON
+    /// with `--output json`
+    #[structopt(name = "export")]
+    Export {
+        /// Where to write the keys. Defaults to stdout
+        #[structopt(name = "FILE", parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Give keys in the project new names
+    #[structopt(name = "rename")]
+    Rename {
+        /// Only print which keys would be renamed
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+
+        /// YAML or JSON file mapping the current names of the keys to their new names
+        #[structopt(name = "FILE", parse(from_os_str))]
+        input: PathBuf,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    match try_main().await {
+        Ok(()) => {}
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(exit_code(&err));
         }
+    }
+}
 
-        Ok(key_names)
+/// See the `EXIT CODES` section of `--help`.
+fn exit_code(err: &Error) -> i32 {
+    if err.is::<PartialFailure>() {
+        2
+    } else if err.is::<Drift>() {
+        4
+    } else if err.is::<LokaliseError>() {
+        3
+    } else {
+        1
     }
+}
 
-    async fn create_keys(&self, project: &Project, keys_to_create: Vec<KeyToAdd>) -> Result<()> {
-        let payload = json!({
-            "keys": keys_to_create.iter().map(|key| {
-                let translation = match &key.translation {
-                    Translation::Singular(text) => json!({
-                        "language_iso": &project.base_language_iso,
-                        "translation": text,
-                    }),
-                    Translation::Plural { singular, plural } => json!({
-                        "language_iso": &project.base_language_iso,
-                        "translation": {
-                            "one": singular,
-                            "other": plural,
-                        }
-                    })
-                };
+/// Some of the keys couldn't be created, updated, or deleted.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct PartialFailure(&'static str);
 
-                let is_plural = match &key.translation {
-                    Translation::Singular(_) => false,
-                    Translation::Plural { .. } => true
-                };
+/// Some of the keys in the input aren't in Lokalise yet, with `--diff-only`.
+#[derive(Debug, thiserror::Error)]
+#[error("{0} of {1} keys aren't in Lokalise yet")]
+struct Drift(usize, usize);
 
-                json!({
-                    "key_name": &key.key,
-                    "translations": [translation],
-                    "is_plural": is_plural,
-                    "platforms": ["ios", "android", "web", "other"],
-                    "tags": &key.tags,
-                })
-            }).collect::<Vec<_>>()
-        });
+async fn try_main() -> Result<()> {
+    let mut opt = Opt::from_args();
+    if opt.version {
+        return print_version(&opt);
+    }
+    if let Some(config) = Config::load().await? {
+        opt.merge_config(config);
+    }
+    if let Some(platform) = &opt.match_platform {
+        opt.check_platform(platform)?;
+    }
+    load_dotenv().await?;
 
-        let resp = self
-            .client
-            .post(&self.url(&format!("/projects/{}/keys", &project.id)))
-            .json(&payload)
-            .send()
-            .await?
-            .json::<serde_json::Value>()
-            .await?;
-
-        let resp_as_keys = serde_json::from_value::<KeysResponse>(resp.clone());
-        let resp_as_error = serde_json::from_value::<ErrorResponse>(resp.clone());
-        let keys = match (resp_as_keys, resp_as_error) {
-            (Ok(keys_resp), Err(_)) => keys_resp.keys,
-            (Err(_), Ok(ErrorResponse { error })) => {
-                use std::fmt::Write;
-
-                let mut msg = String::new();
-                writeln!(msg, "Lokalise request failed").unwrap();
-
-                if error.message == "Unauthorized" {
-                    write!(msg, "Got 401 unauthorized. Please ensure your auth token is correct and has both read and write permissions").unwrap();
-                } else {
-                    write!(msg, "Got {} {}", error.code, error.message).unwrap();
-                }
+    if opt.verbose {
+        env_logger::Builder::new()
+            .filter_module(module_path!(), LevelFilter::Debug)
+            .init();
+    }
 
-                return Err(Error::msg(msg));
-            }
-            (Ok(_), Ok(_)) => {
-                return Err(Error::msg("Lokalise request both failed and succeeded...?"))
+    match &opt.command {
+        Some(Command::ListProjects) => list_projects(&opt).await,
+        Some(Command::Languages) => list_languages(&opt).await,
+        Some(Command::Delete {
+            dry_run,
+            yes,
+            inputs,
+        }) => delete_keys(&opt, inputs, *dry_run, *yes).await,
+        Some(Command::Export { output }) => export_keys(&opt, output.as_deref()).await,
+        Some(Command::Rename { dry_run, input }) => rename_keys(&opt, input, *dry_run).await,
+        None => add_keys(&opt).await,
+    }
+}
+
+/// Set the env vars in `.env` in the current directory, if there is one. Env vars that are already
+/// set win over the ones in the file.
+async fn load_dotenv() -> Result<()> {
+    const FILE_NAME: &str = ".env";
+
+    let contents = match fs::read_to_string(FILE_NAME).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(Error::msg(format!("Failed to read {}: {}", FILE_NAME, err))),
+    };
+
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = match line.find('=') {
+            Some(eq) => (line[..eq].trim(), line[eq + 1..].trim()),
+            None => {
+                return Err(Error::msg(format!(
+                    "Failed to parse {} on line {}, expected `NAME=value`",
+                    FILE_NAME,
+                    idx + 1
+                )))
             }
-            (Err(_), Err(_)) => return Err(Error::msg("Failed to parse lokalise response")),
         };
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|quote| {
+                value
+                    .strip_prefix(*quote)
+                    .and_then(|value| value.strip_suffix(*quote))
+            })
+            .unwrap_or(value);
 
-        let created_keys = keys
-            .into_iter()
-            .map(|key| key.key_name.ios)
-            .collect::<HashSet<_>>();
-
-        let mut keys_created = vec![];
-        let mut keys_not_created = vec![];
-        for key in &keys_to_create {
-            if created_keys.contains(&key.key) {
-                keys_created.push(&key.key);
-            } else {
-                keys_not_created.push(&key.key);
-            }
+        if env::var_os(name).is_none() {
+            env::set_var(name, value);
         }
+    }
 
-        if keys_created.is_empty() && keys_not_created.is_empty() {
-            println!("No keys to create to seems 👀");
-            Ok(())
-        } else {
-            for key in keys_created {
-                println!("✅ {}", key)
-            }
+    Ok(())
+}
 
-            if !keys_not_created.is_empty() {
-                for key in keys_not_created {
-                    println!("❌ {}", key)
-                }
+fn print_version(opt: &Opt) -> Result<()> {
+    let name = env!("CARGO_PKG_NAME");
+    let version = env!("CARGO_PKG_VERSION");
+    let commit = env!("GIT_COMMIT");
+    let target = env!("BUILD_TARGET");
+
+    match opt.output {
+        OutputFormat::Text => println!("{} {} ({}, {})", name, version, commit, target),
+        OutputFormat::Json => {
+            let info = json!({
+                "name": name,
+                "version": version,
+                "git_commit": commit,
+                "target": target,
+            });
+            println!("{}", serde_json::to_string_pretty(&info)?)
+        }
+    }
+    Ok(())
+}
+
+async fn list_projects(opt: &Opt) -> Result<()> {
+    let client = client(opt).await?;
+    let projects = client.projects().await?;
+
+    let rows = projects
+        .iter()
+        .map(|project| {
+            [
+                project.name.as_str(),
+                project.id.as_str(),
+                project.base_language_iso.as_deref().unwrap_or("-"),
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_table(&["NAME", "PROJECT ID", "BASE LANGUAGE"], &rows);
+
+    Ok(())
+}
+
+async fn list_languages(opt: &Opt) -> Result<()> {
+    let client = client(opt).await?;
+    let project = find_project(&client, opt).await?;
+    let languages = client.languages(&project).await?;
 
-                Err(Error::msg("Failed to create some keys"))
+    let rows = languages
+        .iter()
+        .map(|language| {
+            let base = if project.base_language_iso.as_ref() == Some(&language.lang_iso) {
+                "yes"
             } else {
-                Ok(())
-            }
+                "no"
+            };
+            [
+                language.lang_iso.as_str(),
+                language.lang_name.as_str(),
+                base,
+            ]
+        })
+        .collect::<Vec<_>>();
+    print_table(&["ISO", "NAME", "BASE"], &rows);
+
+    Ok(())
+}
+
+async fn export_keys(opt: &Opt, output: Option<&Path>) -> Result<()> {
+    let client = client(opt).await?;
+    let project = find_project(&client, opt).await?;
+    let language_iso = find_language(&client, &project, opt).await?;
+    let data = Data {
+        keys: client
+            .export_keys(&project, &language_iso, opt.page_concurrency)
+            .await?,
+        default_tags: vec![],
+        defaults: None,
+    };
+
+    let mut contents = match opt.output {
+        OutputFormat::Text => serde_yaml::to_string(&data)?,
+        OutputFormat::Json => serde_json::to_string_pretty(&data)?,
+    };
+    if !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    match output {
+        Some(path) => {
+            fs::write(path, contents).await.map_err(|err| {
+                Error::msg(format!("Failed to write {}: {}", path.display(), err))
+            })?;
+            eprintln!("Exported {} keys to {}", data.keys.len(), path.display());
         }
+        None => print!("{}", contents),
+    }
+    Ok(())
+}
+
+fn print_table<S: AsRef<str>>(header: &[&str], rows: &[[S; 3]]) {
+    let mut widths = [0; 3];
+    for (idx, width) in widths.iter_mut().enumerate() {
+        *width = rows
+            .iter()
+            .map(|row| row[idx].as_ref().chars().count())
+            .chain(std::iter::once(header[idx].len()))
+            .max()
+            .unwrap_or(0);
     }
 
-    fn url(&self, url: &str) -> String {
-        format!("https://api.lokalise.com/api2{}", url)
+    println!(
+        "{:<w0$}  {:<w1$}  {}",
+        header[0],
+        header[1],
+        header[2],
+        w0 = widths[0],
+        w1 = widths[1]
+    );
+    for row in rows {
+        println!(
+            "{:<w0$}  {:<w1$}  {}",
+            row[0].as_ref(),
+            row[1].as_ref(),
+            row[2].as_ref(),
+            w0 = widths[0],
+            w1 = widths[1]
+        );
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct Project {
-    #[serde(rename = "project_id")]
-    id: String,
-    name: String,
-    base_language_iso: String,
+async fn client(opt: &Opt) -> Result<LokaliseClient> {
+    let lokalise_token = match (opt.token.clone(), &opt.token_file) {
+        (Some(token), _) => token,
+        (None, Some(path)) => read_token_file(path).await?,
+        (None, None) => env::var("LOKALISE_API_TOKEN").map(ApiToken::from).map_err(|_| {
+            Error::msg(format!(
+                "Missing env var LOKALISE_API_TOKEN. The token can also be given with `--token`, or set in {} or .env",
+                Config::FILE_NAME
+            ))
+        })?,
+    };
+
+    LokaliseClient::new(lokalise_token, client_config(opt))
+}
+
+fn client_config(opt: &Opt) -> ClientConfig {
+    ClientConfig {
+        base_url: opt.api_base_url(),
+        max_retries: opt.max_retries,
+        timeout: Duration::from_secs(opt.timeout),
+        proxy: match (&opt.proxy, opt.no_proxy) {
+            (_, true) => ProxyConfig::Disabled,
+            (Some(url), false) => ProxyConfig::Url(url.clone()),
+            (None, false) => ProxyConfig::FromEnv,
+        },
+        max_idle_connections: opt
+            .max_connections
+            .unwrap_or_else(|| opt.concurrency.max(opt.page_concurrency))
+            .get(),
+        headers: opt
+            .headers
+            .iter()
+            .map(|header| (header.name.clone(), header.value.clone()))
+            .collect(),
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct KeysResponse {
-    keys: Vec<KeyResponse>,
+async fn read_token_file(path: &Path) -> Result<ApiToken> {
+    let contents = fs::read_to_string(path).await.map_err(|err| {
+        Error::msg(format!(
+            "Failed to read the token file {}\n{}",
+            path.display(),
+            err
+        ))
+    })?;
+    let token = contents.trim_end_matches(&['\r', '\n'][..]);
+    if token.trim().is_empty() {
+        return Err(Error::msg(format!(
+            "The token file {} is empty",
+            path.display()
+        )));
+    }
+    Ok(ApiToken::from(token.to_string()))
 }
 
-#[derive(Debug, Deserialize)]
-struct KeyResponse {
-    key_name: KeyName,
+/// Read and parse the keys in all the input files. The files are read concurrently but the keys
+/// are kept in the order the files were given.
+async fn read_keys(opt: &Opt, inputs: &[PathBuf]) -> Result<Vec<KeyToAdd>> {
+    const MAX_CONCURRENT_READS: usize = 16;
+
+    let inputs = expand_inputs(inputs)?;
+    let several_inputs = inputs.len() > 1;
+
+    let keys = stream::iter(&inputs)
+        .map(|input| async move {
+            let file_contents = read_input(input).await?;
+            let data = parse_input(opt, input, &file_contents).map_err(|err| {
+                if several_inputs {
+                    Error::msg(format!("{}: {}", input.display(), err))
+                } else {
+                    err
+                }
+            })?;
+            prepare_keys(opt, data, input).await
+        })
+        .buffered(MAX_CONCURRENT_READS)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    Ok(keys.into_iter().flatten().collect())
 }
 
-#[derive(Debug, Deserialize)]
-struct KeyName {
-    ios: String,
-    android: String,
-    web: String,
-    other: String,
+/// Read the `translation_file`s, and fill in the default platforms, key prefix, and extra tags of
+/// the keys parsed from `input`.
+async fn prepare_keys(opt: &Opt, mut data: Data, input: &Path) -> Result<Vec<KeyToAdd>> {
+    data.resolve_files(input_dir(input)).await?;
+    for key in &mut data.keys {
+        if let Some(screenshot) = &mut key.screenshot {
+            *screenshot = input_dir(input).join(&screenshot);
+        }
+        if let Some(defaults) = &data.defaults {
+            key.apply_defaults(defaults);
+        }
+        if key.platforms.is_none() && !opt.no_platforms {
+            key.platforms = Some(opt.platforms());
+            key.default_platforms = true;
+        }
+        for platform in key.known_platforms() {
+            opt.check_platform(&platform)
+                .map_err(|err| Error::msg(format!("`{}`: {}", key.key, err)))?;
+        }
+        let original_key = key.key.clone();
+        normalize_key_name(opt, &mut key.key);
+        if key.key != original_key {
+            key.original_key = Some(original_key);
+        }
+        if let Some(names) = &mut key.platform_names {
+            names
+                .iter_mut()
+                .for_each(|name| normalize_key_name(opt, name));
+        }
+        let tags = data.default_tags.iter().chain(&opt.tags).cloned().collect();
+        key.tags = merge_tags(&key.tags, tags);
+    }
+    Ok(data.keys)
 }
 
-#[derive(Debug, Deserialize)]
-struct ErrorResponse {
-    error: ErrorResponseInner,
+fn normalize_key_name(opt: &Opt, name: &mut String) {
+    // Names copied from spreadsheets often have stray spaces around them
+    *name = name.trim().to_string();
+    if let Some(prefix) = &opt.key_prefix {
+        name.insert_str(0, prefix);
+    }
+    if opt.lowercase_keys {
+        *name = name.to_lowercase();
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct ErrorResponseInner {
-    code: u32,
-    message: String,
+async fn add_keys(opt: &Opt) -> Result<()> {
+    let mut keys_to_add = match &opt.inline_keys {
+        Some(inline_keys) => {
+            // YAML is a superset of JSON so this handles both
+            let data = parse_yaml(inline_keys, &opt.plural_aliases)?;
+            prepare_keys(opt, data, Path::new("-")).await?
+        }
+        None if opt.inputs.is_empty() => {
+            return Err(Error::msg(
+                "Missing the input FILE containing the keys to add, or `--keys`",
+            ))
+        }
+        None => read_keys(opt, &opt.inputs).await?,
+    };
+    if opt.sort {
+        keys_to_add.sort_by(|a, b| a.key.cmp(&b.key));
+    }
+    if let Some(limit) = opt.limit {
+        keys_to_add.truncate(limit);
+    }
+    validate_keys(&keys_to_add)?;
+    check_empty_translations(opt, &keys_to_add)?;
+    if opt.with_screenshots {
+        check_screenshots(&keys_to_add).await?;
+    }
+
+    if let Some(tag) = &opt.filter_tag {
+        keys_to_add.retain(|key| key.tags.contains(tag));
+    }
+
+    if opt.check {
+        if let Some(language_iso) = opt.language() {
+            validate_languages(&keys_to_add, language_iso)?;
+            check_plural_categories(opt, &keys_to_add, language_iso)?;
+        }
+        return print_check(opt, &keys_to_add);
+    }
+
+    let dry_run = opt.dry_run.map(|dry_run| dry_run.unwrap_or(DryRun::Local));
+
+    if let Some(DryRun::Local) = dry_run {
+        match opt.output {
+            OutputFormat::Text => println!("{:#?}", keys_to_add),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&keys_to_add)?),
+        }
+        return Ok(());
+    }
+
+    let started = Instant::now();
+    let client = client(opt).await?;
+    client.check_token().await?;
+
+    let project = find_project(&client, opt).await?;
+    let language_iso = find_language(&client, &project, opt).await?;
+    validate_languages(&keys_to_add, &language_iso)?;
+    check_plural_categories(opt, &keys_to_add, &language_iso)?;
+
+    // Tags and case collisions are checked against all the keys in the project
+    let case_collisions = opt.check_case_collisions || matches!(dry_run, Some(DryRun::Remote));
+    let names = (!opt.warn_unknown_tags && !case_collisions).then(|| {
+        keys_to_add
+            .iter()
+            .flat_map(|key| existing_names(opt, key))
+            .collect()
+    });
+    let all_keys = existing_keys(&client, &project, opt, names).await?;
+    if opt.warn_unknown_tags {
+        warn_unknown_tags(opt, &keys_to_add, &project_tags(&all_keys))?;
+    }
+    if case_collisions {
+        check_case_collisions(opt, &keys_to_add, &all_keys)?;
+    }
+    let input_order = keys_to_add
+        .iter()
+        .enumerate()
+        .map(|(idx, key)| (key.key.clone(), idx))
+        .collect::<HashMap<_, _>>();
+    let plan = Plan::new(keys_to_add, &all_keys, opt);
+
+    if opt.diff_only || matches!(dry_run, Some(DryRun::Remote)) {
+        match opt.output {
+            OutputFormat::Text => plan.print(),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&plan.to_json())?),
+        }
+        if opt.diff_only && !plan.create.is_empty() {
+            return Err(Drift(plan.create.len(), plan.len()).into());
+        }
+        return Ok(());
+    }
+
+    if !plan.existing.is_empty() {
+        let existing = plan
+            .existing
+            .iter()
+            .map(|key| format!("`{}`", key))
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(Error::msg(format!(
+            "{} of {} keys already exist: {}\nUse `--skip-existing` to only create the new keys, `--update` to also update the existing ones, or `--replace` to delete and create them again",
+            plan.existing.len(),
+            plan.len(),
+            existing
+        )));
+    }
+
+    let Plan {
+        create: mut keys_to_create,
+        update: keys_to_update,
+        replace: mut keys_to_replace,
+        skip: keys_skipped,
+        existing: _,
+    } = plan;
+    // Only for new keys, updating would overwrite the translations the project already has
+    if opt.fill_missing_languages {
+        let languages = client.languages(&project).await?;
+        let new_keys = keys_to_create
+            .iter_mut()
+            .chain(keys_to_replace.iter_mut().map(|(_, key)| key));
+        fill_missing_languages(new_keys, &language_iso, &languages)?;
+    }
+
+    if let Some(DryRun::Payload) = dry_run {
+        keys_to_create.extend(keys_to_replace.into_iter().map(|(_, key)| key));
+        let requests = payload_requests(
+            &project,
+            &language_iso,
+            &keys_to_create,
+            &keys_to_update,
+            opt.chunk_size(),
+        )?;
+        println!("{}", serde_json::to_string_pretty(&requests)?);
+        return Ok(());
+    }
+
+    // Time spent answering doesn't count towards how long the run took
+    let confirming = Instant::now();
+    if !keys_to_replace.is_empty()
+        && !opt.yes
+        && !confirm(&format!(
+            "Delete {} keys from the project '{}'?",
+            keys_to_replace.len(),
+            project.name
+        ))?
+    {
+        return Err(Error::msg("Didn't replace any keys"));
+    }
+    let create_count = keys_to_create.len() + keys_to_replace.len();
+    if create_count > opt.confirm_threshold()
+        && !opt.yes
+        && !confirm(&format!(
+            "Create {} keys in the project '{}'?",
+            create_count, project.name
+        ))?
+    {
+        return Err(Error::msg("Didn't create any keys"));
+    }
+    let confirming = confirming.elapsed();
+
+    if let Some(cache) = opt.cache() {
+        cache.remove_keys(&project).await;
+    }
+    let mut summary = Summary {
+        skipped: keys_skipped,
+        ..Summary::default()
+    };
+    let delete_error = if keys_to_replace.is_empty() {
+        None
+    } else {
+        replace_keys(
+            &client,
+            &project,
+            opt,
+            keys_to_replace,
+            &mut keys_to_create,
+            &mut summary,
+        )
+        .await?
+    };
+
+    let batch = BatchOptions::new(
+        opt.chunk_size(),
+        opt.concurrency,
+        opt.fail_fast,
+        progress_bar(opt, keys_to_create.len() + keys_to_update.len()),
+    );
+    summary.merge(
+        client
+            .create_keys(&project, &language_iso, &keys_to_create, &batch)
+            .await?,
+    );
+    summary.merge(
+        client
+            .update_keys(&project, &language_iso, &keys_to_update, &batch)
+            .await?,
+    );
+    if opt.verify && !summary.created.is_empty() {
+        verify_created(&client, &project, opt, &mut summary).await?;
+    }
+    summary.sort(&input_order);
+
+    batch.progress.finish_and_clear();
+
+    let screenshots_failed = if opt.with_screenshots {
+        !upload_screenshots(&client, &project, &keys_to_create, &summary, opt).await?
+    } else {
+        false
+    };
+    if summary.failed.is_empty() && !screenshots_failed && delete_error.is_none() {
+        summary.elapsed = Some(started.elapsed() - confirming);
+    }
+
+    if let Some(path) = &opt.report {
+        write_report(path, &project, &summary).await?;
+    }
+
+    match opt.output {
+        OutputFormat::Text if opt.quiet => summary.print_counts(),
+        OutputFormat::Text => summary.print(opt.verbose, opt.markers()),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary.to_json())?),
+    }
+
+    if let Some(err) = delete_error {
+        return Err(err);
+    }
+    if summary.failed.is_empty() && !screenshots_failed {
+        Ok(())
+    } else if summary.failed.is_empty() {
+        Err(PartialFailure("Failed to upload some screenshots").into())
+    } else if opt.fail_fast {
+        Err(PartialFailure("Stopped after the first chunk with keys that failed").into())
+    } else if opt.update {
+        Err(PartialFailure("Failed to create or update some keys").into())
+    } else {
+        Err(PartialFailure("Failed to create some keys").into())
+    }
+}
+
+/// Delete the keys to replace, and queue the ones that are gone to be created again. Lokalise only
+/// says whether it deleted a whole chunk, so the keys are listed again to find out. The ones that
+/// are still there are failed, so they aren't created twice.
+///
+/// If deleting a chunk fails, the error is returned instead of failing right away, so the keys
+/// that were already deleted are still created again.
+async fn replace_keys(
+    client: &LokaliseClient,
+    project: &Project,
+    opt: &Opt,
+    keys_to_replace: Vec<(u64, KeyToAdd)>,
+    keys_to_create: &mut Vec<KeyToAdd>,
+    summary: &mut Summary,
+) -> Result<Option<Error>> {
+    let ids = keys_to_replace
+        .iter()
+        .map(|(id, key)| (*id, key.key.clone()))
+        .collect::<Vec<_>>();
+    let delete_error = client
+        .delete_keys(project, &ids, opt.chunk_size())
+        .await
+        .err();
+
+    let names = keys_to_replace
+        .iter()
+        .flat_map(|(_, key)| existing_names(opt, key))
+        .collect::<Vec<_>>();
+    let remaining = if names.iter().any(|name| name.contains(',')) {
+        client.all_keys(project, opt.page_concurrency, None).await
+    } else {
+        client.keys_named(project, &names, None).await
+    };
+    let remaining = remaining.map_err(|err| {
+        let keys = keys_to_replace
+            .iter()
+            .map(|(_, key)| format!("`{}`", key.key))
+            .collect::<Vec<_>>();
+        Error::msg(format!(
+            "Failed to check which keys were deleted to replace them, these may be gone from the project: {}\n{}",
+            keys.join(", "),
+            err
+        ))
+    })?;
+    let remaining = remaining.values().map(|key| key.id).collect::<HashSet<_>>();
+
+    for (id, key) in keys_to_replace {
+        if remaining.contains(&id) {
+            summary.failed.push(FailedKey {
+                key: key.key,
+                reason: Some("Failed to delete the existing key to replace it".to_string()),
+            });
+        } else {
+            keys_to_create.push(key);
+        }
+    }
+    Ok(delete_error.map(Error::from))
+}
+
+/// Check that the screenshots exist and are images Lokalise accepts, before uploading anything.
+async fn check_screenshots(keys: &[KeyToAdd]) -> Result<()> {
+    for key in keys {
+        let path = match &key.screenshot {
+            Some(path) => path,
+            None => continue,
+        };
+
+        screenshot_mime_type(path).ok_or_else(|| {
+            Error::msg(format!(
+                "The screenshot of `{}` must be a PNG or JPEG file, got {}",
+                key.key,
+                path.display()
+            ))
+        })?;
+        fs::metadata(path).await.map_err(|err| {
+            Error::msg(format!(
+                "Failed to read the screenshot of `{}` from {}: {}",
+                key.key,
+                path.display(),
+                err
+            ))
+        })?;
+    }
+
+    Ok(())
+}
+
+fn screenshot_mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        _ => None,
+    }
+}
+
+/// Upload the screenshots of the keys that were created, printing why for the ones that failed.
+/// Returns whether all of them were uploaded.
+async fn upload_screenshots(
+    client: &LokaliseClient,
+    project: &Project,
+    keys_to_create: &[KeyToAdd],
+    summary: &Summary,
+    opt: &Opt,
+) -> Result<bool> {
+    let created = summary
+        .created
+        .iter()
+        .map(|created| (created.key.as_str(), created.key_id))
+        .collect::<HashMap<_, _>>();
+    let screenshots = keys_to_create.iter().filter_map(|key| {
+        let path = key.screenshot.as_ref()?;
+        let key_id = *created.get(key.key.as_str())?;
+        Some((key, key_id, path))
+    });
+
+    let mut uploads = stream::iter(screenshots)
+        .map(|(key, key_id, path)| async move {
+            let mime_type = screenshot_mime_type(path).unwrap_or("image/png");
+            let res = match fs::read(path).await {
+                Ok(image) => client
+                    .upload_screenshot(project, key_id, &key.key, mime_type, &image)
+                    .await
+                    .map_err(Error::from),
+                Err(err) => Err(Error::msg(format!(
+                    "Failed to read {}: {}",
+                    path.display(),
+                    err
+                ))),
+            };
+            (key, res)
+        })
+        .buffer_unordered(opt.concurrency.get());
+
+    let mut all_uploaded = true;
+    while let Some((key, res)) = uploads.next().await {
+        if let Err(err) = res {
+            eprintln!("Failed to upload the screenshot of `{}`: {}", key.key, err);
+            all_uploaded = false;
+        }
+    }
+
+    Ok(all_uploaded)
+}
+
+/// The requests that would be sent to create and update the keys, for `--dry-run=payload`.
+fn payload_requests(
+    project: &Project,
+    language_iso: &str,
+    keys_to_create: &[KeyToAdd],
+    keys_to_update: &[(u64, KeyToAdd)],
+    chunk_size: NonZeroUsize,
+) -> Result<serde_json::Value> {
+    let path = format!("/projects/{}/keys", project.id);
+    let create = keys_to_create.chunks(chunk_size.get()).map(|chunk| {
+        Ok(json!({
+            "method": "POST",
+            "path": path,
+            "body": create_payload(language_iso, chunk)?,
+        }))
+    });
+    let update = keys_to_update.chunks(chunk_size.get()).map(|chunk| {
+        Ok(json!({
+            "method": "PUT",
+            "path": path,
+            "body": update_payload(language_iso, chunk)?,
+        }))
+    });
+    Ok(json!(create.chain(update).collect::<Result<Vec<_>>>()?))
+}
+
+/// Fetch the keys of the project again, and count the keys that Lokalise said were created but
+/// aren't there as failed.
+async fn verify_created(
+    client: &LokaliseClient,
+    project: &Project,
+    opt: &Opt,
+    summary: &mut Summary,
+) -> Result<()> {
+    let all_keys = client.all_keys(project, opt.page_concurrency, None).await?;
+    // Keys with a name of their own on every platform aren't listed under `key`
+    let ids = all_keys.values().map(|key| key.id).collect::<HashSet<_>>();
+
+    let (created, missing) = std::mem::take(&mut summary.created)
+        .into_iter()
+        .partition::<Vec<_>, _>(|created| ids.contains(&created.key_id));
+    summary.created = created;
+    summary
+        .failed
+        .extend(missing.into_iter().map(|missing| FailedKey {
+            key: missing.key,
+            reason: Some("Not in the project after creating it".to_string()),
+        }));
+
+    Ok(())
+}
+
+/// Print how many keys were checked by `--check`.
+fn print_check(opt: &Opt, keys: &[KeyToAdd]) -> Result<()> {
+    let plural = keys
+        .iter()
+        .filter(|key| key.translation.is_plural())
+        .count();
+    match opt.output {
+        OutputFormat::Text => println!("{} keys are valid, {} of them plural", keys.len(), plural),
+        OutputFormat::Json => {
+            let counts = json!({ "counts": { "keys": keys.len(), "plural": plural } });
+            println!("{}", serde_json::to_string_pretty(&counts)?)
+        }
+    }
+    Ok(())
+}
+
+async fn delete_keys(opt: &Opt, inputs: &[PathBuf], dry_run: bool, yes: bool) -> Result<()> {
+    let keys = read_keys(opt, inputs).await?;
+    validate_keys(&keys)?;
+
+    let client = client(opt).await?;
+    client.check_token().await?;
+
+    let project = find_project(&client, opt).await?;
+    let names = keys
+        .iter()
+        .flat_map(|key| existing_names(opt, key))
+        .collect();
+    let all_keys = existing_keys(&client, &project, opt, Some(names)).await?;
+
+    let mut summary = DeleteSummary::default();
+    let mut keys_to_delete = vec![];
+    for key in keys {
+        let existing = existing_names(opt, &key)
+            .into_iter()
+            .find_map(|name| all_keys.get(name));
+        match existing {
+            Some(existing) => keys_to_delete.push((existing.id, key.key)),
+            None => summary.missing.push(key.key),
+        }
+    }
+
+    if dry_run {
+        summary.deleted = keys_to_delete.into_iter().map(|(_, key)| key).collect();
+        match opt.output {
+            OutputFormat::Text => summary.print_dry_run(),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary.to_json())?),
+        }
+        return Ok(());
+    }
+
+    if !keys_to_delete.is_empty()
+        && !yes
+        && !confirm(&format!(
+            "Delete {} keys from the project '{}'?",
+            keys_to_delete.len(),
+            project.name
+        ))?
+    {
+        return Err(Error::msg("Didn't delete any keys"));
+    }
+
+    if let Some(cache) = opt.cache() {
+        cache.remove_keys(&project).await;
+    }
+    summary.merge(
+        client
+            .delete_keys(&project, &keys_to_delete, opt.chunk_size())
+            .await?,
+    );
+
+    match opt.output {
+        OutputFormat::Text if opt.quiet => summary.print_counts(),
+        OutputFormat::Text => summary.print(opt.markers()),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary.to_json())?),
+    }
+
+    if summary.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(PartialFailure("Failed to delete some keys").into())
+    }
+}
+
+async fn rename_keys(opt: &Opt, input: &Path, dry_run: bool) -> Result<()> {
+    let contents = read_input(input)
+        .await
+        .map_err(|err| Error::msg(format!("Failed to read {}: {}", input.display(), err)))?;
+    let renames = serde_yaml::from_str::<BTreeMap<String, String>>(&contents)
+        .map_err(|err| Error::msg(format!("Failed to parse {}: {}", input.display(), err)))?
+        .into_iter()
+        .map(|(mut from, mut to)| {
+            normalize_key_name(opt, &mut from);
+            normalize_key_name(opt, &mut to);
+            Rename { from, to }
+        })
+        .filter(|rename| rename.from != rename.to)
+        .collect::<Vec<_>>();
+    validate_renames(&renames)?;
+
+    let client = client(opt).await?;
+    client.check_token().await?;
+
+    let project = find_project(&client, opt).await?;
+    let names = renames
+        .iter()
+        .flat_map(|rename| vec![rename.from.as_str(), rename.to.as_str()])
+        .collect();
+    let all_keys = existing_keys(&client, &project, opt, Some(names)).await?;
+
+    let taken = renames
+        .iter()
+        .filter(|rename| all_keys.contains_key(&rename.to))
+        .map(|rename| format!("`{}` -> `{}`", rename.from, rename.to))
+        .collect::<Vec<_>>();
+    if !taken.is_empty() {
+        return Err(Error::msg(format!(
+            "The new names of these keys are already used in the project: {}",
+            taken.join(", ")
+        )));
+    }
+
+    let mut summary = RenameSummary::default();
+    let mut keys_to_rename = vec![];
+    for rename in renames {
+        match all_keys.get(&rename.from) {
+            Some(existing) => keys_to_rename.push((existing.id, rename)),
+            None => summary.missing.push(rename.from),
+        }
+    }
+
+    if dry_run {
+        summary.renamed = keys_to_rename
+            .into_iter()
+            .map(|(_, rename)| rename)
+            .collect();
+        match opt.output {
+            OutputFormat::Text => summary.print_dry_run(),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary.to_json())?),
+        }
+        return Ok(());
+    }
+
+    if let Some(cache) = opt.cache() {
+        cache.remove_keys(&project).await;
+    }
+    let renamed = client
+        .rename_keys(&project, &keys_to_rename, opt.chunk_size())
+        .await?;
+    summary.renamed = renamed.renamed;
+    summary.failed = renamed.failed;
+
+    match opt.output {
+        OutputFormat::Text if opt.quiet => summary.print_counts(),
+        OutputFormat::Text => summary.print(opt.markers()),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&summary.to_json())?),
+    }
+
+    if summary.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(PartialFailure("Failed to rename some keys").into())
+    }
+}
+
+/// Check that no two keys get the same new name.
+fn validate_renames(renames: &[Rename]) -> Result<()> {
+    let mut seen = HashMap::new();
+    for rename in renames {
+        if rename.to.is_empty() {
+            return Err(Error::msg(format!(
+                "The new name of `{}` is empty",
+                rename.from
+            )));
+        }
+        if let Some(other) = seen.insert(&rename.to, &rename.from) {
+            return Err(Error::msg(format!(
+                "Both `{}` and `{}` would be renamed to `{}`",
+                other, rename.from, rename.to
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Ask a yes or no question on the terminal, defaulting to no.
+fn confirm(question: &str) -> Result<bool> {
+    use std::io::Write;
+
+    if !atty::is(atty::Stream::Stdin) {
+        return Err(Error::msg(format!(
+            "{}\nCan't ask for confirmation when stdin isn't a terminal, pass `--yes` to go ahead anyway",
+            question
+        )));
+    }
+
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn progress_bar(opt: &Opt, len: usize) -> ProgressBar {
+    let show = !opt.no_progress
+        && !opt.quiet
+        && matches!(opt.output, OutputFormat::Text)
+        && atty::is(atty::Stream::Stdout);
+    if !show {
+        return ProgressBar::hidden();
+    }
+
+    let progress = ProgressBar::new(len as u64);
+    progress.set_style(
+        ProgressStyle::default_bar().template("[{elapsed_precise}] {wide_bar} {pos}/{len} keys"),
+    );
+    progress
+}
+
+async fn write_report(path: &Path, project: &Project, summary: &Summary) -> Result<()> {
+    let mut report = summary.to_json();
+    report["project"] = json!(&project.name);
+    report["timestamp"] = json!(humantime::format_rfc3339_seconds(SystemTime::now()).to_string());
+
+    let contents = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::to_string(&report)?,
+        _ => serde_json::to_string_pretty(&report)?,
+    };
+
+    fs::write(path, contents).await.map_err(|err| {
+        Error::msg(format!(
+            "Failed to write report to {}: {}",
+            path.display(),
+            err
+        ))
+    })
+}
+
+/// What to do with each of the keys in the input file, given the keys already in the project.
+#[derive(Debug, Default)]
+struct Plan {
+    create: Vec<KeyToAdd>,
+    update: Vec<(u64, KeyToAdd)>,
+    /// Keys to delete and create again, with `--replace`.
+    replace: Vec<(u64, KeyToAdd)>,
+    skip: Vec<String>,
+    /// Keys that already exist, with neither `--update` nor `--skip-existing` given.
+    existing: Vec<String>,
+}
+
+impl Plan {
+    fn new(keys: Vec<KeyToAdd>, all_keys: &HashMap<String, ExistingKey>, opt: &Opt) -> Self {
+        let mut plan = Plan::default();
+
+        for key in keys {
+            let existing = existing_names(opt, &key)
+                .into_iter()
+                .find_map(|name| all_keys.get(name));
+            match existing {
+                Some(existing) if opt.update => {
+                    let mut key = key;
+                    if opt.tag_merge {
+                        key.tags = merge_tags(&existing.tags, key.tags);
+                    }
+                    plan.update.push((existing.id, key))
+                }
+                Some(existing) if opt.replace => plan.replace.push((existing.id, key)),
+                Some(_) if opt.skip_existing => plan.skip.push(key.key),
+                Some(_) => plan.existing.push(key.key),
+                None => plan.create.push(key),
+            }
+        }
+
+        plan
+    }
+
+    fn print(&self) {
+        let sections = [
+            (
+                "Would create",
+                self.create.iter().map(|key| &key.key).collect::<Vec<_>>(),
+            ),
+            (
+                "Would update",
+                self.update.iter().map(|(_, key)| &key.key).collect(),
+            ),
+            (
+                "Would replace",
+                self.replace.iter().map(|(_, key)| &key.key).collect(),
+            ),
+            ("Would skip", self.skip.iter().collect()),
+            ("Already exist", self.existing.iter().collect()),
+        ];
+
+        for (title, keys) in sections.iter() {
+            if keys.is_empty() {
+                continue;
+            }
+
+            println!("{} ({}):", title, keys.len());
+            for key in keys {
+                println!("  {}", key);
+            }
+        }
+    }
+
+    /// How many keys there are in the input file.
+    fn len(&self) -> usize {
+        self.create.len()
+            + self.update.len()
+            + self.replace.len()
+            + self.skip.len()
+            + self.existing.len()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "create": self.create.iter().map(|key| &key.key).collect::<Vec<_>>(),
+            "update": self.update.iter().map(|(_, key)| &key.key).collect::<Vec<_>>(),
+            "replace": self.replace.iter().map(|(_, key)| &key.key).collect::<Vec<_>>(),
+            "skip": &self.skip,
+            "existing": &self.existing,
+        })
+    }
+}
+
+/// The names a key counts as existing under if the project has a key with any of them. Those are
+/// its names on all the platforms, or only the one given with `--match-platform`.
+fn existing_names<'a>(opt: &Opt, key: &'a KeyToAdd) -> Vec<&'a str> {
+    match &opt.match_platform {
+        Some(platform) => vec![key.name_on(platform)],
+        None => key.names(),
+    }
+}
+
+/// Union of the existing tags and the new ones, keeping the existing tags first.
+fn merge_tags(existing: &[String], new: Vec<String>) -> Vec<String> {
+    let mut tags = existing.to_vec();
+    for tag in new {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DryRun {
+    Local,
+    Remote,
+    Payload,
+}
+
+impl FromStr for DryRun {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(DryRun::Local),
+            "remote" => Ok(DryRun::Remote),
+            "payload" => Ok(DryRun::Payload),
+            other => Err(format!("Unknown dry run mode `{}`", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(format!("Unknown output format `{}`", other)),
+        }
+    }
+}
+
+/// A header given with `--header`.
+#[derive(Debug, Clone)]
+struct Header {
+    name: String,
+    value: String,
+}
+
+impl FromStr for Header {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.find(':') {
+            Some(idx) => (s[..idx].trim(), s[idx + 1..].trim()),
+            None => return Err(format!("Expected `KEY:VALUE`, got `{}`", s)),
+        };
+        reqwest::header::HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| format!("Invalid header name `{}`", name))?;
+        reqwest::header::HeaderValue::from_str(value)
+            .map_err(|_| format!("Invalid value for the header `{}`", name))?;
+
+        Ok(Header {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+async fn find_project(client: &LokaliseClient, opt: &Opt) -> Result<Project> {
+    match (&opt.project(), &opt.project_id) {
+        (None, Some(project_id)) => {
+            let name = format!("project-{}", project_id);
+            cached(opt.cache().as_ref(), &name, client.project(project_id)).await
+        }
+        (Some(name), None) => {
+            let projects = cached(opt.cache().as_ref(), "projects", client.projects()).await?;
+            let suggestion = closest_match(name, projects.iter().map(|p| p.name.as_str()))
+                .map(|suggestion| format!(", did you mean '{}'?", suggestion))
+                .unwrap_or_default();
+            projects
+                .into_iter()
+                .find(|p| &p.name == name)
+                .ok_or_else(|| {
+                    Error::msg(format!(
+                        "No project name '{}' was found{}",
+                        name, suggestion
+                    ))
+                })
+        }
+        (Some(_), Some(_)) => Err(Error::msg(
+            "Only one of `--project` and `--project-id` can be given",
+        )),
+        (None, None) => Err(Error::msg(format!(
+            "Either `--project` or `--project-id` must be given, `project` set in {}, or the `LOKALISE_PROJECT` env var set",
+            Config::FILE_NAME
+        ))),
+    }
+}
+
+/// The language the main translation of each key is attached to, checking that it's enabled in
+/// the project when it's not the base language.
+async fn find_language(client: &LokaliseClient, project: &Project, opt: &Opt) -> Result<String> {
+    let language_iso = match (opt.language(), &project.base_language_iso) {
+        (Some(language_iso), Some(base)) if language_iso == base => return Ok(base.clone()),
+        (Some(language_iso), _) => language_iso,
+        (None, Some(base)) => return Ok(base.clone()),
+        (None, None) => {
+            return Err(Error::msg(format!(
+                "The project '{}' has no base language, use `--language` to pick one",
+                project.name
+            )))
+        }
+    };
+
+    let languages = client.languages(project).await?;
+    if languages.iter().any(|lang| &lang.lang_iso == language_iso) {
+        if opt.base_language.is_some() {
+            warn(
+                opt,
+                format!(
+                    "Using `{}` as the base language instead of the project's `{}`",
+                    language_iso,
+                    project.base_language_iso.as_deref().unwrap_or("-")
+                ),
+            )?;
+        }
+        return Ok(language_iso.clone());
+    }
+
+    let enabled = languages
+        .iter()
+        .map(|lang| lang.lang_iso.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(Error::msg(format!(
+        "The language `{}` isn't enabled in the project '{}'. It has: {}",
+        language_iso, project.name, enabled
+    )))
+}
+
+/// Find the candidate closest to `name`, if any are close enough to likely be a typo.
+fn closest_match<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    const MAX_DISTANCE: usize = 3;
+
+    candidates
+        .map(|candidate| {
+            let distance = levenshtein(&name.to_lowercase(), &candidate.to_lowercase());
+            (distance, candidate)
+        })
+        .filter(|(distance, _)| *distance <= MAX_DISTANCE)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev_row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = prev_row[j] + if a_char == *b_char { 0 } else { 1 };
+            let insertion = row[j] + 1;
+            let deletion = prev_row[j + 1] + 1;
+            row.push(substitution.min(insertion).min(deletion));
+        }
+        prev_row = row;
+    }
+
+    prev_row[b.len()]
+}
+
+/// Expand the glob patterns among the input files, for shells that don't do it themselves.
+fn expand_inputs(inputs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for input in inputs {
+        let pattern = input.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            paths.push(input.clone());
+            continue;
+        }
+
+        let matches = glob::glob(&pattern)
+            .map_err(|err| Error::msg(format!("Invalid glob pattern `{}`: {}", pattern, err)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        if matches.is_empty() {
+            return Err(Error::msg(format!("No files match `{}`", pattern)));
+        }
+        paths.extend(matches);
+    }
+    Ok(paths)
+}
+
+async fn read_input(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut contents = String::new();
+        tokio::io::stdin().read_to_string(&mut contents).await?;
+        Ok(contents)
+    } else {
+        Ok(fs::read_to_string(path).await?)
+    }
+}
+
+/// The directory that paths in an input file are relative to.
+fn input_dir(input: &Path) -> &Path {
+    match input.parent() {
+        Some(dir) if input != Path::new("-") => dir,
+        _ => Path::new(""),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum InputFormat {
+    Yaml,
+    Json,
+    Csv,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yaml" => Ok(InputFormat::Yaml),
+            "json" => Ok(InputFormat::Json),
+            "csv" => Ok(InputFormat::Csv),
+            other => Err(format!("Unknown input format `{}`", other)),
+        }
+    }
+}
+
+fn parse_input(opt: &Opt, path: &Path, contents: &str) -> Result<Data> {
+    let aliases = &opt.plural_aliases;
+    let format = opt
+        .format
+        .or_else(|| match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Some(InputFormat::Json),
+            Some("yaml") | Some("yml") => Some(InputFormat::Yaml),
+            Some("csv") => Some(InputFormat::Csv),
+            _ => None,
+        });
+
+    match format {
+        Some(InputFormat::Json) => parse_json(contents, aliases),
+        Some(InputFormat::Yaml) => parse_yaml(contents, aliases),
+        Some(InputFormat::Csv) => parse_csv(contents),
+        None => parse_yaml(contents, aliases).or_else(|yaml_err| {
+            parse_json(contents, aliases).map_err(|json_err| {
+                Error::msg(format!(
+                    "Couldn't detect the input format from the file extension\n{}\n{}",
+                    yaml_err, json_err
+                ))
+            })
+        }),
+    }
+}
+
+fn parse_yaml(contents: &str, plural_aliases: &BTreeMap<String, String>) -> Result<Data> {
+    let error =
+        |err: &dyn std::fmt::Display| Error::msg(format!("Failed to parse input as YAML: {}", err));
+    if plural_aliases.is_empty() {
+        return serde_yaml::from_str(contents).map_err(|err| error(&err));
+    }
+
+    let mut data = serde_yaml::from_str(contents).map_err(|err| error(&err))?;
+    rename_plural_forms(&mut data, plural_aliases).map_err(|err| error(&err))?;
+    serde_json::from_value(data).map_err(|err| error(&err))
+}
+
+fn parse_json(contents: &str, plural_aliases: &BTreeMap<String, String>) -> Result<Data> {
+    let error =
+        |err: &dyn std::fmt::Display| Error::msg(format!("Failed to parse input as JSON: {}", err));
+    if plural_aliases.is_empty() {
+        return serde_json::from_str(contents).map_err(|err| error(&err));
+    }
+
+    let mut data = serde_json::from_str(contents).map_err(|err| error(&err))?;
+    rename_plural_forms(&mut data, plural_aliases).map_err(|err| error(&err))?;
+    serde_json::from_value(data).map_err(|err| error(&err))
+}
+
+/// Rename the plural forms of the keys, and of their other languages, from the names in
+/// `plural_aliases` to the CLDR categories they stand for. This happens before the keys are
+/// deserialized since that's where the categories are checked.
+fn rename_plural_forms(
+    data: &mut serde_json::Value,
+    plural_aliases: &BTreeMap<String, String>,
+) -> Result<(), String> {
+    let rename = |forms: Option<&mut serde_json::Value>| {
+        let forms = match forms.and_then(|forms| forms.as_object_mut()) {
+            Some(forms) => forms,
+            None => return Ok(()),
+        };
+        for (alias, category) in plural_aliases {
+            if let Some(text) = forms.remove(alias) {
+                if forms.contains_key(category) {
+                    return Err(format!(
+                        "plural category `{}` is given twice, once as `{}`",
+                        category, alias
+                    ));
+                }
+                forms.insert(category.clone(), text);
+            }
+        }
+        Ok(())
+    };
+
+    let keys = match data.get_mut("keys").and_then(|keys| keys.as_array_mut()) {
+        Some(keys) => keys,
+        None => return Ok(()),
+    };
+    for key in keys {
+        rename(key.get_mut("translations"))?;
+        if let Some(languages) = key.get_mut("languages").and_then(|l| l.as_object_mut()) {
+            for translation in languages.values_mut() {
+                rename(Some(translation))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_csv(contents: &str) -> Result<Data> {
+    #[derive(Debug, Deserialize)]
+    struct Row {
+        key: String,
+        #[serde(default)]
+        translation: Option<String>,
+        #[serde(default)]
+        translation_one: Option<String>,
+        #[serde(default)]
+        translation_other: Option<String>,
+        #[serde(default)]
+        tags: Option<String>,
+        #[serde(default)]
+        description: Option<String>,
+        #[serde(default)]
+        char_limit: Option<NonZeroU32>,
+        #[serde(default)]
+        plural_name: Option<String>,
+        #[serde(default)]
+        screenshot: Option<PathBuf>,
+        #[serde(default)]
+        hidden: Option<bool>,
+        #[serde(default)]
+        archived: Option<bool>,
+    }
+
+    let mut reader = csv::Reader::from_reader(contents.as_bytes());
+    let mut keys = vec![];
+
+    for row in reader.deserialize::<Row>() {
+        let row =
+            row.map_err(|err| Error::msg(format!("Failed to parse input as CSV: {}", err)))?;
+
+        let translation = match (row.translation, row.translation_one, row.translation_other) {
+            (_, Some(singular), Some(plural)) => {
+                Translation::Plural(PluralForms::new(singular, plural))
+            }
+            (Some(text), _, _) => Translation::Singular(text),
+            _ => {
+                return Err(Error::msg(format!(
+                    "The key `{}` needs either a `translation` or both `translation_one` and `translation_other`",
+                    row.key
+                )))
+            }
+        };
+
+        let tags = row
+            .tags
+            .iter()
+            .flat_map(|tags| tags.split(';'))
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| tag.to_string())
+            .collect();
+
+        keys.push(KeyToAdd {
+            key: row.key,
+            original_key: None,
+            platform_names: None,
+            translation,
+            languages: BTreeMap::new(),
+            tags,
+            platforms: None,
+            default_platforms: false,
+            description: row.description,
+            char_limit: row.char_limit,
+            plural_name: row.plural_name,
+            comments: vec![],
+            screenshot: row.screenshot,
+            hidden: row.hidden.unwrap_or(false),
+            archived: row.archived.unwrap_or(false),
+        });
+    }
+
+    Ok(Data {
+        keys,
+        default_tags: vec![],
+        defaults: None,
+    })
+}
+
+fn validate_keys(keys: &[KeyToAdd]) -> Result<()> {
+    let mut seen = HashSet::new();
+    let mut duplicates = vec![];
+    for key in keys {
+        if !seen.insert(&key.key) && !duplicates.contains(&&key.key) {
+            duplicates.push(&key.key);
+        }
+    }
+    if !duplicates.is_empty() {
+        let duplicates = duplicates
+            .iter()
+            .map(|&name| {
+                // Mention the names in the input if they only collide after the prefix and such
+                let colliding = keys.iter().filter(|key| &key.key == name);
+                if colliding.clone().all(|key| key.original_key.is_none()) {
+                    return format!("`{}`", name);
+                }
+                let originals = colliding
+                    .map(|key| format!("`{}`", key.original_key.as_ref().unwrap_or(&key.key)))
+                    .collect::<Vec<_>>();
+                format!("`{}` (from {})", name, originals.join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(Error::msg(format!(
+            "The input contains these keys more than once: {}",
+            duplicates
+        )));
+    }
+
+    for key in keys {
+        if key.key.trim().is_empty() {
+            return Err(Error::msg("Keys must have a name"));
+        }
+
+        if key.tags.iter().any(|tag| tag.trim().is_empty()) {
+            return Err(Error::msg(format!(
+                "The key `{}` has an empty tag",
+                key.key
+            )));
+        }
+
+        if matches!(&key.platforms, Some(platforms) if platforms.is_empty()) {
+            return Err(Error::msg(format!(
+                "The key `{}` must have at least one platform",
+                key.key
+            )));
+        }
+
+        let is_plural = key.translation.is_plural();
+        if key.plural_name.is_some() && !is_plural {
+            return Err(Error::msg(format!(
+                "The key `{}` has a `plural_name` but isn't plural",
+                key.key
+            )));
+        }
+
+        for (language_iso, translation) in &key.languages {
+            if translation.is_plural() != is_plural {
+                return Err(Error::msg(format!(
+                    "The key `{}` mixes singular and plural translations (see `{}`)",
+                    key.key, language_iso
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a warning, or fail with it with `--strict`.
+fn warn(opt: &Opt, message: String) -> Result<()> {
+    if opt.strict {
+        return Err(Error::msg(format!(
+            "{}\nFailing because of `--strict`",
+            message
+        )));
+    }
+    eprintln!("Warning: {}", message);
+    Ok(())
+}
+
+/// Warn about keys with empty translations.
+fn check_empty_translations(opt: &Opt, keys: &[KeyToAdd]) -> Result<()> {
+    let empty = keys
+        .iter()
+        .filter(|key| {
+            key.translation.has_empty_text()
+                || key
+                    .languages
+                    .values()
+                    .any(|translation| translation.has_empty_text())
+        })
+        .map(|key| format!("`{}`", key.key))
+        .collect::<Vec<_>>();
+    if empty.is_empty() {
+        return Ok(());
+    }
+
+    warn(
+        opt,
+        format!("These keys have empty translations: {}", empty.join(", ")),
+    )
+}
+
+/// Warn about incoming keys with the same name as an existing one apart from casing.
+fn check_case_collisions(
+    opt: &Opt,
+    keys: &[KeyToAdd],
+    all_keys: &HashMap<String, ExistingKey>,
+) -> Result<()> {
+    let existing = all_keys
+        .keys()
+        .map(|name| (name.to_lowercase(), name))
+        .collect::<HashMap<_, _>>();
+
+    let collisions = keys
+        .iter()
+        .filter(|key| !all_keys.contains_key(&key.key))
+        .filter_map(|key| {
+            existing
+                .get(&key.key.to_lowercase())
+                .map(|name| format!("`{}` (the project has `{}`)", key.key, name))
+        })
+        .collect::<Vec<_>>();
+    if collisions.is_empty() {
+        return Ok(());
+    }
+
+    warn(
+        opt,
+        format!(
+            "These keys only differ in casing from keys in the project: {}",
+            collisions.join(", ")
+        ),
+    )
+}
+
+/// Warn about the tags of the keys that aren't in `known_tags`.
+fn warn_unknown_tags(opt: &Opt, keys: &[KeyToAdd], known_tags: &BTreeSet<String>) -> Result<()> {
+    let unknown = keys
+        .iter()
+        .flat_map(|key| &key.tags)
+        .filter(|tag| !known_tags.contains(*tag))
+        .collect::<BTreeSet<_>>();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+
+    let unknown = unknown
+        .iter()
+        .map(|tag| format!("`{}`", tag))
+        .collect::<Vec<_>>();
+    warn(
+        opt,
+        format!(
+            "These tags aren't used in the project yet: {}",
+            unknown.join(", ")
+        ),
+    )
+}
+
+/// Check that no key also has a `languages` entry for the language its main translation is in.
+fn validate_languages(keys: &[KeyToAdd], language_iso: &str) -> Result<()> {
+    for key in keys {
+        if key.languages.contains_key(language_iso) {
+            return Err(Error::msg(format!(
+                "The key `{}` has two translations for `{}`, remove it from `languages`",
+                key.key, language_iso
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Give every key a translation for each of the project's languages, using the text of the main
+/// translation for the ones it doesn't have.
+fn fill_missing_languages<'a>(
+    keys: impl IntoIterator<Item = &'a mut KeyToAdd>,
+    language_iso: &str,
+    languages: &[Language],
+) -> Result<()> {
+    for key in keys {
+        for language in languages {
+            if language.lang_iso == language_iso || key.languages.contains_key(&language.lang_iso) {
+                continue;
+            }
+
+            let translation = match &key.translation {
+                Translation::Singular(text) => LanguageTranslation::Singular(text.clone()),
+                Translation::Plural(forms) => {
+                    LanguageTranslation::Plural(forms.for_language(&language.lang_iso))
+                }
+                Translation::File(path) => {
+                    return Err(LokaliseError::UnreadTranslationFile { path: path.clone() }.into())
+                }
+            };
+            key.languages.insert(language.lang_iso.clone(), translation);
+        }
+    }
+    Ok(())
+}
+
+/// Warn about plural keys with CLDR plural categories that their language doesn't have, like
+/// `one` in Japanese which only has `other`.
+fn check_plural_categories(opt: &Opt, keys: &[KeyToAdd], language_iso: &str) -> Result<()> {
+    let mut unused = vec![];
+    for key in keys {
+        let translations = std::iter::once((language_iso, key.translation.plural_forms())).chain(
+            key.languages.iter().map(|(language_iso, translation)| {
+                (language_iso.as_str(), translation.plural_forms())
+            }),
+        );
+
+        for (language_iso, forms) in translations {
+            let (forms, supported) = match (forms, plural_categories(language_iso)) {
+                (Some(forms), Some(supported)) => (forms, supported),
+                _ => continue,
+            };
+
+            let categories = forms
+                .categories()
+                .filter(|category| !supported.contains(category))
+                .collect::<Vec<_>>();
+            if !categories.is_empty() {
+                unused.push(format!(
+                    "`{}` has {} in `{}`, which only uses {}",
+                    key.key,
+                    categories.join(", "),
+                    language_iso,
+                    supported.join(", ")
+                ));
+            }
+        }
+    }
+    if unused.is_empty() {
+        return Ok(());
+    }
+
+    warn(
+        opt,
+        format!(
+            "These keys have plural categories their language doesn't use: {}",
+            unused.join("; ")
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lokalise_bulk_add_keys::KeyWithId;
+
+    fn opt(args: &[&str]) -> Opt {
+        Opt::from_iter_safe(std::iter::once("lokalise-bulk-add-keys").chain(args.iter().copied()))
+            .unwrap()
+    }
+
+    fn keys(yaml: &str) -> Vec<KeyToAdd> {
+        parse_yaml(yaml, &BTreeMap::new()).unwrap().keys
+    }
+
+    fn existing(keys: &[(&str, u64)]) -> HashMap<String, ExistingKey> {
+        keys.iter()
+            .map(|(name, id)| {
+                let key = ExistingKey {
+                    id: *id,
+                    tags: vec![],
+                };
+                (name.to_string(), key)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keys_exist_if_the_project_has_any_of_their_platform_names() {
+        let keys = || {
+            keys(
+                "
+                keys:
+                  - key: title
+                    translation: Title
+                    platform_names: { ios: title_ios, android: title_android, web: title_web, other: title_other }
+                  - key: body
+                    translation: Body
+                ",
+            )
+        };
+        let all_keys = existing(&[("title_android", 1)]);
+
+        let plan = Plan::new(keys(), &all_keys, &opt(&["--update"]));
+        assert_eq!(plan.update.len(), 1);
+        assert_eq!(plan.update[0].0, 1);
+        assert_eq!(plan.update[0].1.key, "title");
+        assert_eq!(plan.create.len(), 1);
+
+        let plan = Plan::new(keys(), &all_keys, &opt(&["--match-platform", "web"]));
+        assert!(plan.existing.is_empty());
+        assert_eq!(plan.create.len(), 2);
+    }
+
+    #[test]
+    fn english_plurals_use_one_and_other() {
+        let strict = opt(&["--strict"]);
+        let keys = keys(
+            "
+            keys:
+              - key: apples
+                translations: { one: One apple, other: '{count} apples' }
+              - key: pears
+                translations: { singular: One pear, plural: '{count} pears' }
+            ",
+        );
+
+        check_plural_categories(&strict, &keys, "en").unwrap();
+    }
+
+    #[test]
+    fn english_plurals_with_other_categories_are_warned_about() {
+        let keys = keys(
+            "
+            keys:
+              - key: apples
+                translations: { one: One apple, few: A few apples, other: '{count} apples' }
+            ",
+        );
+
+        check_plural_categories(&opt(&[]), &keys, "en").unwrap();
+        let err = check_plural_categories(&opt(&["--strict"]), &keys, "en").unwrap_err();
+        assert!(
+            err.to_string().contains("`apples` has few in `en`"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn arabic_plurals_use_all_six_categories() {
+        let keys = keys(
+            "
+            keys:
+              - key: apples
+                translations:
+                  zero: No apples
+                  one: One apple
+                  two: Two apples
+                  few: A few apples
+                  many: Many apples
+                  other: '{count} apples'
+            ",
+        );
+
+        check_plural_categories(&opt(&["--strict"]), &keys, "ar").unwrap();
+        assert!(check_plural_categories(&opt(&["--strict"]), &keys, "en").is_err());
+    }
+
+    #[test]
+    fn singular_and_plural_are_only_a_warning_for_languages_without_one() {
+        let keys = keys(
+            "
+            keys:
+              - key: apples
+                translations: { singular: One apple, plural: '{count} apples' }
+            ",
+        );
+
+        check_plural_categories(&opt(&[]), &keys, "ja").unwrap();
+        assert!(check_plural_categories(&opt(&["--strict"]), &keys, "ja").is_err());
+    }
+
+    #[test]
+    fn csv_rows_are_parsed() {
+        let data = parse_csv(
+            "key,translation,translation_one,translation_other,tags\n\
+             title,Title,,,onboarding; home\n\
+             apples,,One apple,{count} apples,\n",
+        )
+        .unwrap();
+
+        let title = &data.keys[0];
+        assert_eq!(title.key, "title");
+        assert!(matches!(&title.translation, Translation::Singular(text) if text == "Title"));
+        assert_eq!(title.tags, ["onboarding", "home"]);
+
+        let apples = &data.keys[1];
+        let forms = apples.translation.plural_forms().unwrap();
+        assert_eq!(forms.categories().collect::<Vec<_>>(), ["one", "other"]);
+        assert!(apples.tags.is_empty());
+    }
+
+    #[test]
+    fn csv_rows_need_a_translation() {
+        let err = parse_csv("key,translation_one\napples,One apple\n").unwrap_err();
+
+        assert!(
+            err.to_string().contains("The key `apples` needs either"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn yaml_and_json_inputs_are_parsed() {
+        let opt = opt(&[]);
+        let yaml = "keys:\n  - key: title\n    translation: Title\n";
+        let json = r#"{ "keys": [{ "key": "title", "translation": "Title" }] }"#;
+
+        for (path, contents) in [("keys.yaml", yaml), ("keys.json", json), ("keys", json)] {
+            let data = parse_input(&opt, Path::new(path), contents).unwrap();
+            assert_eq!(data.keys.len(), 1, "{}", path);
+            assert_eq!(data.keys[0].key, "title");
+            assert!(matches!(
+                &data.keys[0].translation,
+                Translation::Singular(text) if text == "Title"
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_errors_name_the_parser() {
+        let opt = opt(&[]);
+        let contents = "keys: [";
+
+        let err = parse_input(&opt, Path::new("keys.yaml"), contents).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Failed to parse input as YAML: "),
+            "{}",
+            err
+        );
+
+        let err = parse_input(&opt, Path::new("keys.json"), contents).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Failed to parse input as JSON: "),
+            "{}",
+            err
+        );
+
+        let err = parse_input(&opt, Path::new("keys.txt"), contents).unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("Failed to parse input as YAML: "), "{}", err);
+        assert!(err.contains("Failed to parse input as JSON: "), "{}", err);
+    }
+
+    async fn prepared(opt: &Opt, yaml: &str) -> Result<Vec<KeyToAdd>> {
+        let data = parse_yaml(yaml, &opt.plural_aliases).unwrap();
+        prepare_keys(opt, data, Path::new("keys.yaml")).await
+    }
+
+    #[tokio::test]
+    async fn keys_are_on_every_platform_unless_they_list_their_own() {
+        let keys = prepared(
+            &opt(&[]),
+            "
+            keys:
+              - key: title
+                translation: Title
+                platforms: [web]
+              - key: body
+                translation: Body
+            ",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(keys[0].platforms, Some(vec![Platform::Web]));
+        assert!(!keys[0].default_platforms);
+        assert_eq!(keys[1].platforms, Some(Platform::all()));
+        assert!(keys[1].default_platforms);
+    }
+
+    #[tokio::test]
+    async fn unknown_platforms_are_rejected() {
+        let yaml = "keys:\n  - key: title\n    translation: Title\n    platforms: [wbe]\n";
+
+        let err = prepared(&opt(&[]), yaml).await.unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "`title`: Unknown platform `wbe`, add it to `custom_platforms` in lokalise.toml if the project has it"
+        );
+    }
+
+    #[test]
+    fn keys_listed_twice_are_rejected() {
+        let keys = keys(
+            "
+            keys:
+              - key: title
+                translation: Title
+              - key: body
+                translation: Body
+              - key: title
+                translation: Other title
+            ",
+        );
+
+        let err = validate_keys(&keys).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "The input contains these keys more than once: `title`"
+        );
+        validate_keys(&keys[..2]).unwrap();
+    }
+
+    #[test]
+    fn levenshtein_counts_edits() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("undo", "undo"), 0);
+        assert_eq!(levenshtein("undo", ""), 4);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("Ünd", "und"), 1);
+    }
+
+    #[test]
+    fn closest_match_suggests_likely_typos() {
+        let projects = ["Undo", "Undo Web", "Marketing"];
+
+        assert_eq!(
+            closest_match("undo", projects.iter().copied()),
+            Some("Undo")
+        );
+        assert_eq!(
+            closest_match("Undo Wbe", projects.iter().copied()),
+            Some("Undo Web")
+        );
+        assert_eq!(closest_match("Finance", projects.iter().copied()), None);
+    }
+
+    #[test]
+    fn merged_tags_keep_the_existing_ones_first() {
+        let existing = ["b".to_string(), "a".to_string()];
+
+        let tags = merge_tags(&existing, vec!["c".to_string(), "a".to_string()]);
+
+        assert_eq!(tags, ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn updated_keys_only_keep_their_tags_with_tag_merge() {
+        let keys = || keys("keys:\n  - key: title\n    translation: Title\n    tags: [new]\n");
+        let mut all_keys = existing(&[("title", 1)]);
+        all_keys.get_mut("title").unwrap().tags = vec!["old".to_string()];
+
+        let plan = Plan::new(keys(), &all_keys, &opt(&["--update"]));
+        assert_eq!(plan.update[0].1.tags, ["new"]);
+
+        let plan = Plan::new(keys(), &all_keys, &opt(&["--update", "--tag-merge"]));
+        assert_eq!(plan.update[0].1.tags, ["old", "new"]);
+    }
+
+    fn config(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn options_on_the_command_line_win_over_the_config_file_and_env_vars() {
+        let config = || {
+            config(
+                r#"
+                project = "Config project"
+                api_base_url = "http://config"
+                chunk_size = 100
+                "#,
+            )
+        };
+        env::set_var("LOKALISE_API_BASE_URL", "http://env");
+
+        let mut cli = opt(&["-p", "Cli project", "--api-base-url", "http://cli"]);
+        cli.merge_config(config());
+        assert_eq!(cli.project(), Some("Cli project".to_string()));
+        assert_eq!(cli.api_base_url(), "http://cli");
+        assert_eq!(cli.chunk_size().get(), 100);
+
+        let mut defaults = opt(&[]);
+        defaults.merge_config(config());
+        assert_eq!(defaults.project(), Some("Config project".to_string()));
+        assert_eq!(defaults.api_base_url(), "http://config");
+
+        let mut env_only = opt(&[]);
+        env_only.merge_config(Config::default());
+        assert_eq!(env_only.api_base_url(), "http://env");
+        assert_eq!(env_only.chunk_size().get(), 500);
+        env::remove_var("LOKALISE_API_BASE_URL");
+    }
+
+    #[test]
+    fn exit_codes_tell_failures_apart() {
+        let partial = Error::new(PartialFailure("Some keys couldn't be created"));
+        assert_eq!(exit_code(&partial), 2);
+        let partial = partial.context("While adding keys");
+        assert_eq!(exit_code(&partial), 2);
+
+        assert_eq!(exit_code(&Error::new(Drift(1, 2))), 4);
+        assert_eq!(exit_code(&Error::new(LokaliseError::Unauthorized)), 3);
+        assert_eq!(exit_code(&Error::msg("The input is invalid")), 1);
+    }
+
+    fn project() -> Project {
+        serde_json::from_value(json!({ "project_id": "123.abc", "name": "App" })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn reports_are_written_as_json_or_yaml() {
+        let dir = tempfile::tempdir().unwrap();
+        let summary = Summary {
+            created: vec![KeyWithId {
+                key: "title".to_string(),
+                key_id: 1,
+            }],
+            skipped: vec!["body".to_string()],
+            ..Summary::default()
+        };
+
+        let json_path = dir.path().join("report.json");
+        write_report(&json_path, &project(), &summary)
+            .await
+            .unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(report["project"], json!("App"));
+        assert_eq!(report["created"], json!([{ "key": "title", "key_id": 1 }]));
+        assert_eq!(report["counts"]["total"], json!(2));
+        assert!(report["timestamp"].is_string());
+
+        let yaml_path = dir.path().join("report.yaml");
+        write_report(&yaml_path, &project(), &summary)
+            .await
+            .unwrap();
+        let yaml: serde_json::Value =
+            serde_yaml::from_str(&std::fs::read_to_string(&yaml_path).unwrap()).unwrap();
+        assert_eq!(yaml["skipped"], json!(["body"]));
+
+        let err = write_report(
+            &dir.path().join("missing/report.json"),
+            &project(),
+            &summary,
+        )
+        .await
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("Failed to write report to "),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_from_several_files_are_combined() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("first.yaml");
+        let second = dir.path().join("second.json");
+        std::fs::write(&first, "keys:\n  - key: title\n    translation: Title\n").unwrap();
+        std::fs::write(
+            &second,
+            r#"{ "keys": [{ "key": "body", "translation": "Body" }, { "key": "title", "translation": "Title" }] }"#,
+        )
+        .unwrap();
+
+        let keys = read_keys(&opt(&[]), &[first.clone(), second.clone()])
+            .await
+            .unwrap();
+
+        let names = keys.iter().map(|key| key.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["title", "body", "title"]);
+        let err = validate_keys(&keys).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The input contains these keys more than once: `title`"
+        );
+
+        std::fs::write(&second, "{").unwrap();
+        let err = read_keys(&opt(&[]), &[first, second.clone()])
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with(&format!("{}: ", second.display())),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn default_tags_and_tag_options_are_added_to_every_key() {
+        let yaml = "
+            default_tags: release_42
+            keys:
+              - key: title
+                translation: Title
+                tags: [onboarding, release_42]
+              - key: body
+                translation: Body
+            ";
+
+        let keys = prepared(&opt(&["--tag", "ci", "--tag", "bulk"]), yaml)
+            .await
+            .unwrap();
+
+        assert_eq!(keys[0].tags, ["onboarding", "release_42", "ci", "bulk"]);
+        assert_eq!(keys[1].tags, ["release_42", "ci", "bulk"]);
+    }
+
+    #[tokio::test]
+    async fn key_prefix_is_prepended_to_every_name() {
+        let yaml = "
+            keys:
+              - key: title
+                translation: Title
+                platform_names: { web: titleWeb }
+            ";
+
+        let keys = prepared(&opt(&["--key-prefix", "onboarding."]), yaml)
+            .await
+            .unwrap();
+
+        assert_eq!(keys[0].key, "onboarding.title");
+        assert_eq!(keys[0].original_key.as_deref(), Some("title"));
+        assert_eq!(keys[0].name_on(&Platform::Web), "onboarding.titleWeb");
+    }
+
+    #[tokio::test]
+    async fn no_platforms_leaves_them_out_of_the_payload() {
+        let yaml = "
+            keys:
+              - key: title
+                translation: Title
+              - key: body
+                translation: Body
+                platforms: [web]
+            ";
+
+        let keys = prepared(&opt(&["--no-platforms"]), yaml).await.unwrap();
+        let payload = create_payload("en", &keys).unwrap();
+
+        assert!(payload["keys"][0].get("platforms").is_none());
+        assert_eq!(payload["keys"][1]["platforms"], json!(["web"]));
+    }
+
+    #[tokio::test]
+    async fn concurrent_reads_keep_the_order_of_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs = (0..40)
+            .map(|idx| {
+                let path = dir.path().join(format!("keys_{}.yaml", idx));
+                // Bigger files early on so they tend to finish after the later ones
+                let keys = (0..(40 - idx) * 10)
+                    .map(|key| format!("  - {{ key: key_{}_{}, translation: Text }}\n", idx, key))
+                    .collect::<String>();
+                std::fs::write(&path, format!("keys:\n{}", keys)).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+        let opt = opt(&[]);
+
+        let concurrent = read_keys(&opt, &inputs).await.unwrap();
+        let mut sequential = vec![];
+        for input in &inputs {
+            sequential.extend(read_keys(&opt, std::slice::from_ref(input)).await.unwrap());
+        }
+
+        let names = |keys: &[KeyToAdd]| keys.iter().map(|key| key.key.clone()).collect::<Vec<_>>();
+        assert_eq!(concurrent.len(), 8200);
+        assert_eq!(names(&concurrent), names(&sequential));
+    }
+
+    #[test]
+    fn missing_languages_get_the_main_translation() {
+        let languages = serde_json::from_value::<Vec<Language>>(json!([
+            { "lang_iso": "en", "lang_name": "English" },
+            { "lang_iso": "fr", "lang_name": "French" },
+            { "lang_iso": "ja", "lang_name": "Japanese" },
+        ]))
+        .unwrap();
+        let mut keys = keys(
+            "
+            keys:
+              - key: title
+                translation: Title
+              - key: body
+                translation: Body
+                languages: { fr: Corps }
+              - key: apples
+                translations: { one: One apple, other: Apples }
+            ",
+        );
+
+        fill_missing_languages(&mut keys, "en", &languages).unwrap();
+
+        let text = |key: &KeyToAdd, language: &str| match &key.languages[language] {
+            LanguageTranslation::Singular(text) => text.clone(),
+            LanguageTranslation::Plural(forms) => forms.categories().collect::<Vec<_>>().join(","),
+        };
+        assert_eq!(keys[0].languages.keys().collect::<Vec<_>>(), ["fr", "ja"]);
+        assert_eq!(text(&keys[0], "fr"), "Title");
+        assert_eq!(text(&keys[0], "ja"), "Title");
+        assert_eq!(text(&keys[1], "fr"), "Corps");
+        assert_eq!(text(&keys[1], "ja"), "Body");
+        assert_eq!(text(&keys[2], "fr"), "many,one,other");
+        assert_eq!(text(&keys[2], "ja"), "other");
+
+        let mut unread = self::keys("keys:\n  - key: terms\n    translation_file: terms.md\n");
+        let err = fill_missing_languages(&mut unread, "en", &languages).unwrap_err();
+        assert!(err.is::<LokaliseError>(), "{}", err);
+        assert!(err.to_string().contains("terms.md"), "{}", err);
+    }
+
+    #[test]
+    fn empty_translations_are_warned_about() {
+        let keys = keys(
+            "
+            keys:
+              - key: title
+                translation: '  '
+              - key: apples
+                translations: { one: One apple, other: '' }
+              - key: body
+                translation: Body
+                languages: { fr: '' }
+              - key: footer
+                translation: Footer
+            ",
+        );
+        let strict = opt(&["--strict"]);
+
+        check_empty_translations(&opt(&[]), &keys).unwrap();
+        let err = check_empty_translations(&strict, &keys).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "These keys have empty translations: `title`, `apples`, `body`\nFailing because of `--strict`"
+        );
+        check_empty_translations(&strict, &keys[3..]).unwrap();
+    }
+
+    #[test]
+    fn idle_connections_are_kept_for_max_connections() {
+        let config = client_config(&opt(&["--max-connections", "2", "--concurrency", "8"]));
+        assert_eq!(config.max_idle_connections, 2);
+
+        let config = client_config(&opt(&["--concurrency", "8", "--page-concurrency", "3"]));
+        assert_eq!(config.max_idle_connections, 8);
+
+        let config = client_config(&opt(&["--page-concurrency", "6"]));
+        assert_eq!(config.max_idle_connections, 6);
+    }
+
+    #[tokio::test]
+    async fn key_names_are_trimmed_and_lowercased() {
+        let yaml = "
+            keys:
+              - key: '  Onboarding.Title '
+                translation: Title
+                platform_names: { web: ' TitleWeb' }
+              - key: body
+                translation: Body
+            ";
+
+        let keys = prepared(&opt(&["--lowercase-keys"]), yaml).await.unwrap();
+        assert_eq!(keys[0].key, "onboarding.title");
+        assert_eq!(keys[0].name_on(&Platform::Web), "titleweb");
+        assert_eq!(keys[1].key, "body");
+        assert_eq!(keys[1].original_key, None);
+
+        let keys = prepared(&opt(&[]), yaml).await.unwrap();
+        assert_eq!(keys[0].key, "Onboarding.Title");
+    }
+
+    #[tokio::test]
+    async fn keys_can_collide_after_normalizing() {
+        let yaml = "
+            keys:
+              - key: title
+                translation: Title
+              - key: ' Title'
+                translation: Title
+            ";
+
+        let keys = prepared(&opt(&[]), yaml).await.unwrap();
+        validate_keys(&keys).unwrap();
+
+        let keys = prepared(&opt(&["--lowercase-keys"]), yaml).await.unwrap();
+        assert!(validate_keys(&keys).is_err());
+    }
+
+    #[tokio::test]
+    async fn collisions_after_normalizing_name_the_keys_in_the_input() {
+        let yaml = "
+            keys:
+              - { key: Title, translation: Title }
+              - { key: title, translation: Title }
+              - { key: body, translation: Body }
+              - { key: body, translation: Body }
+            ";
+
+        let keys = prepared(&opt(&["--key-prefix", "home.", "--lowercase-keys"]), yaml)
+            .await
+            .unwrap();
+        let err = validate_keys(&keys).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "The input contains these keys more than once: `home.title` (from `Title`, `title`), `home.body` (from `body`, `body`)"
+        );
+    }
+
+    #[test]
+    fn plural_forms_can_have_configured_names() {
+        let forms = |data: &Data| {
+            data.keys
+                .iter()
+                .map(|key| serde_json::to_value(key.translation.plural_forms()).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let aliases = config("[plural_aliases]\ncount_one = \"one\"\ncount_other = \"other\"\n")
+            .plural_aliases;
+        let data = parse_yaml(
+            "
+            keys:
+              - key: apples
+                translations: { count_one: One apple, count_other: '{count} apples' }
+                languages:
+                  fr: { count_one: Une pomme, count_other: '{count} pommes' }
+            ",
+            &aliases,
+        )
+        .unwrap();
+        assert_eq!(
+            forms(&data),
+            [json!({ "one": "One apple", "other": "{count} apples" })]
+        );
+        assert_eq!(
+            serde_json::to_value(&data.keys[0].languages["fr"]).unwrap(),
+            json!({ "one": "Une pomme", "other": "{count} pommes" })
+        );
+
+        let aliases = config("[plural_aliases]\n1 = \"one\"\nn = \"other\"\n").plural_aliases;
+        let data = parse_json(
+            r#"{ "keys": [{ "key": "pears", "translations": { "1": "One pear", "n": "Pears" } }] }"#,
+            &aliases,
+        )
+        .unwrap();
+        assert_eq!(
+            forms(&data),
+            [json!({ "one": "One pear", "other": "Pears" })]
+        );
+
+        let err = parse_json(
+            r#"{ "keys": [{ "key": "pears", "translations": { "1": "One pear", "one": "A pear", "n": "Pears" } }] }"#,
+            &aliases,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("plural category `one` is given twice, once as `1`"));
+        assert!(parse_json(
+            r#"{ "keys": [{ "key": "pears", "translations": { "count_one": "One pear", "n": "Pears" } }] }"#,
+            &aliases,
+        )
+        .is_err());
+    }
 }
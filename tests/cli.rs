@@ -0,0 +1,1409 @@
+mod common;
+
+use common::{Cli, Lokalise, MockServer, Response, PROJECT_ID, TOKEN};
+use serde_json::json;
+use std::sync::Arc;
+
+fn keys_path() -> String {
+    format!("/api2/projects/{}/keys", PROJECT_ID)
+}
+
+#[test]
+fn languages_takes_the_project_after_the_subcommand() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+
+    for args in [
+        &["languages", "-p", "App"][..],
+        &["languages", "--project-id", "123.abc"],
+        &["--project", "App", "languages"],
+    ] {
+        let run = cli.run(args);
+
+        assert_eq!(run.code, Some(0), "{:?}", run);
+        assert_eq!(
+            run.stdout,
+            "ISO  NAME     BASE\nen   English  yes\nfr   French   no\n"
+        );
+    }
+}
+
+#[test]
+fn keys_with_their_own_name_on_every_platform_are_verified() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+            platform_names: { ios: title_ios, android: title_android, web: title_web, other: title_other }
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--verify", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "✅ title\n");
+    assert!(lokalise.key_named("title_ios").is_some());
+
+    // Now that the project has it under its own names it already exists
+    let run = cli.run(&["-p", "App", "keys.yaml"]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(run.stderr.contains("1 of 1 keys already exist: `title`"));
+}
+
+#[test]
+fn exported_keys_can_be_added_again() {
+    let (server, _) = MockServer::lokalise(vec![
+        json!({
+            "key_id": 1,
+            "key_name": { "ios": "apples", "android": "apples", "web": "apples", "other": "apples" },
+            "platforms": ["ios", "android", "web", "other"],
+            "is_plural": true,
+            "translations": [
+                { "language_iso": "en", "translation": "" },
+                { "language_iso": "fr", "translation": r#"{"one":"Une pomme","other":"Des pommes"}"# },
+            ],
+        }),
+        json!({
+            "key_id": 2,
+            "key_name": { "ios": "title", "android": "title", "web": "title_web", "other": "title" },
+            "platforms": ["ios", "web"],
+            "tags": ["onboarding"],
+            "description": "Shown at the top",
+            "translations": [
+                { "language_iso": "en", "translation": "Title" },
+                { "language_iso": "fr", "translation": "Titre" },
+            ],
+        }),
+    ]);
+    let cli = Cli::new(&server);
+
+    for (format, file) in [("text", "keys.yaml"), ("json", "keys.json")] {
+        let run = cli.run(&["export", "-p", "App", "--output", format, file]);
+        assert_eq!(run.code, Some(0), "{:?}", run);
+        assert_eq!(run.stderr, format!("Exported 2 keys to {}\n", file));
+
+        let run = cli.run(&["--check", "--language", "en", "--strict", file]);
+        assert_eq!(run.code, Some(1), "{:?}", run);
+        assert!(
+            run.stderr
+                .contains("These keys have empty translations: `apples`"),
+            "{:?}",
+            run
+        );
+
+        let run = cli.run(&["--check", "--language", "en", file]);
+        assert_eq!(run.code, Some(0), "{:?}", run);
+        assert_eq!(run.stdout, "2 keys are valid, 1 of them plural\n");
+    }
+
+    let exported = std::fs::read_to_string(cli.dir.path().join("keys.yaml")).unwrap();
+    assert!(!exported.contains("languages: {}"), "{}", exported);
+    assert!(!exported.contains("tags: []"), "{}", exported);
+}
+
+#[test]
+fn custom_platforms_have_to_be_configured() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+            platforms: [watch]
+            platform_names: { watch: title_watch }
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "keys.yaml"]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(
+        run.stderr.contains(
+            "`title`: Unknown platform `watch`, add it to `custom_platforms` in lokalise.toml"
+        ),
+        "{:?}",
+        run
+    );
+    assert!(lokalise.keys().is_empty());
+
+    cli.write("lokalise.toml", "custom_platforms = [\"watch\"]\n");
+    let run = cli.run(&["-p", "App", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    let key = lokalise.key_named("title").unwrap();
+    assert_eq!(key["platforms"], json!(["watch"]));
+    assert_eq!(key["key_name"]["watch"], "title_watch");
+}
+
+#[test]
+fn filter_tag_only_shows_keys_with_the_tag() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+            tags: [onboarding]
+          - key: body
+            translation: Body
+            tags: [home]
+        ",
+    );
+
+    let run = cli.run(&[
+        "--dry-run",
+        "--filter-tag",
+        "onboarding",
+        "--output",
+        "json",
+        "keys.yaml",
+    ]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    let keys: serde_json::Value = serde_json::from_str(&run.stdout).unwrap();
+    let names = keys
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|key| key["key"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["title"]);
+}
+
+#[test]
+fn upsert_creates_new_keys_and_updates_existing_ones() {
+    let (server, lokalise) = MockServer::lokalise(vec![common::key(7, "title", &["old"])]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: New title
+            tags: [new]
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--upsert", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "✅ body\n🔄 title\n");
+    let title = lokalise.key_named("title").unwrap();
+    assert_eq!(title["key_id"], 7);
+    assert_eq!(title["tags"], json!(["new"]));
+    assert_eq!(
+        title["translations"],
+        json!([{ "language_iso": "en", "translation": "New title" }])
+    );
+    assert!(lokalise.key_named("body").is_some());
+    assert_eq!(server.requests_to("PUT", &keys_path()).len(), 1);
+    assert_eq!(server.requests_to("POST", &keys_path()).len(), 1);
+}
+
+#[test]
+fn match_platform_only_compares_that_platforms_names() {
+    let (server, _) = MockServer::lokalise(vec![json!({
+        "key_id": 1,
+        "key_name": { "ios": "legacy_title", "android": "legacy_title", "web": "title", "other": "legacy_title" },
+        "platforms": ["ios", "web"],
+    })]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "keys:\n  - key: title\n    translation: Title\n",
+    );
+
+    let run = cli.run(&[
+        "-p",
+        "App",
+        "--dry-run=remote",
+        "--match-platform",
+        "web",
+        "keys.yaml",
+    ]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "Already exist (1):\n  title\n");
+
+    let run = cli.run(&[
+        "-p",
+        "App",
+        "--dry-run=remote",
+        "--match-platform",
+        "ios",
+        "keys.yaml",
+    ]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "Would create (1):\n  title\n");
+}
+
+#[test]
+fn existing_keys_are_counted_and_can_be_skipped() {
+    let (server, lokalise) = MockServer::lokalise(vec![common::key(1, "title", &[])]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "keys.yaml"]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(
+        run.stderr
+            .starts_with("1 of 2 keys already exist: `title`\n"),
+        "{:?}",
+        run
+    );
+    assert!(server.requests_to("POST", &keys_path()).is_empty());
+
+    let run = cli.run(&["-p", "App", "--skip-existing", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "✅ body\n⏭️ title\n");
+    assert!(
+        run.stderr
+            .contains("1 of 2 keys already exist and were skipped\n"),
+        "{:?}",
+        run
+    );
+    assert_eq!(lokalise.keys().len(), 2);
+}
+
+#[test]
+fn quiet_only_prints_the_counts() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "keys:\n  - key: title\n    translation: Title\n",
+    );
+
+    let run = cli.run(&["-p", "App", "--quiet", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "Created 1, updated 0, skipped 0, failed 0\n");
+    assert_eq!(run.stderr, "");
+}
+
+#[test]
+fn keys_in_the_file_are_deleted() {
+    let (server, lokalise) = MockServer::lokalise(vec![
+        common::key(1, "title", &[]),
+        common::key(2, "body", &[]),
+    ]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+          - key: missing
+            translation: Missing
+        ",
+    );
+
+    let run = cli.run(&["delete", "-p", "App", "--dry-run", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(
+        run.stdout,
+        "Would delete (1):\n  title\nNot in the project (1):\n  missing\n"
+    );
+    assert!(server.requests_to("DELETE", &keys_path()).is_empty());
+
+    let run = cli.run(&["delete", "-p", "App", "--yes", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "🗑️ title\n❓ missing (not in the project)\n");
+    let deletes = server.requests_to("DELETE", &keys_path());
+    assert_eq!(deletes.len(), 1);
+    assert_eq!(deletes[0].json(), json!({ "keys": [1] }));
+    assert!(lokalise.key_named("title").is_none());
+    assert!(lokalise.key_named("body").is_some());
+}
+
+#[test]
+fn keys_can_be_given_inline() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+
+    let run = cli.run(&[
+        "-p",
+        "App",
+        "--keys",
+        r#"{ "keys": [{ "key": "title", "translation": "Title" }] }"#,
+    ]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "✅ title\n");
+
+    let run = cli.run(&[
+        "-p",
+        "App",
+        "--keys",
+        "keys: [{ key: body, translation: Body, tags: [home] }]",
+    ]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(lokalise.key_named("body").unwrap()["tags"], json!(["home"]));
+}
+
+#[test]
+fn sort_orders_the_keys_by_name() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - { key: c_title, translation: C }
+          - { key: a_title, translation: A }
+          - { key: b_title, translation: B }
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--dry-run=remote", "--sort", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(
+        run.stdout,
+        "Would create (3):\n  a_title\n  b_title\n  c_title\n"
+    );
+
+    let run = cli.run(&["-p", "App", "--dry-run=remote", "keys.yaml"]);
+    assert_eq!(
+        run.stdout,
+        "Would create (3):\n  c_title\n  a_title\n  b_title\n"
+    );
+}
+
+#[test]
+fn check_validates_the_file_without_contacting_lokalise() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "valid.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+          - key: apples
+            translations: { one: One apple, other: Apples }
+            plural_name: count
+        ",
+    );
+    cli.write(
+        "invalid.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+            plural_name: count
+        ",
+    );
+
+    let run = cli.run(&["--check", "valid.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "2 keys are valid, 1 of them plural\n");
+
+    let run = cli.run(&["--check", "--output", "json", "valid.yaml"]);
+    let counts: serde_json::Value = serde_json::from_str(&run.stdout).unwrap();
+    assert_eq!(counts, json!({ "counts": { "keys": 2, "plural": 1 } }));
+
+    let run = cli.run(&["--check", "invalid.yaml"]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert_eq!(run.stdout, "");
+    assert_eq!(
+        run.stderr,
+        "The key `title` has a `plural_name` but isn't plural\n"
+    );
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn projects_and_keys_are_cached_between_runs() {
+    let (server, _) = MockServer::lokalise(vec![common::key(1, "title", &[])]);
+    let cli = Cli::new(&server);
+    cli.write("keys.yaml", "keys:\n  - key: body\n    translation: Body\n");
+    let args = [
+        "-p",
+        "App",
+        "--dry-run=remote",
+        "--cache-dir",
+        "cache",
+        "keys.yaml",
+    ];
+    let fetches = || {
+        let keys = server.requests_to("GET", &keys_path()).len();
+        let projects = server
+            .requests_to("GET", "/api2/projects")
+            .iter()
+            .filter(|req| req.query("limit").is_none())
+            .count();
+        (projects, keys)
+    };
+
+    for _ in 0..2 {
+        let run = cli.run(&args);
+        assert_eq!(run.code, Some(0), "{:?}", run);
+        assert_eq!(run.stdout, "Would create (1):\n  body\n");
+    }
+    assert_eq!(fetches(), (1, 1));
+    assert!(cli.dir.path().join("cache/projects.json").exists());
+
+    let run = cli.run(&[&args[..], &["--no-cache"]].concat());
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(fetches(), (2, 2));
+
+    let run = cli.run(&[&args[..], &["--cache-ttl", "0"]].concat());
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(fetches(), (3, 3));
+}
+
+#[test]
+fn fail_fast_stops_after_a_rejected_chunk() {
+    let lokalise = Arc::new(Lokalise::new(vec![]));
+    let handler = lokalise.clone();
+    let server = MockServer::start(move |req| {
+        if req.method == "POST" && req.body.contains("\"second\"") {
+            return Response::error(400, "Invalid translation");
+        }
+        handler.handle(req)
+    });
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - { key: first, translation: First }
+          - { key: second, translation: Second }
+          - { key: third, translation: Third }
+        ",
+    );
+    let args = [
+        "-p",
+        "App",
+        "--chunk-size",
+        "1",
+        "--concurrency",
+        "1",
+        "keys.yaml",
+    ];
+
+    let run = cli.run(&args);
+    assert_eq!(run.code, Some(2), "{:?}", run);
+    assert_eq!(
+        run.stdout,
+        "✅ first\n✅ third\n❌ second: Got 400 Invalid translation\n"
+    );
+    assert!(
+        run.stderr.ends_with("Failed to create some keys\n"),
+        "{:?}",
+        run
+    );
+
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - { key: fourth, translation: Fourth }
+          - { key: second, translation: Second }
+          - { key: fifth, translation: Fifth }
+        ",
+    );
+    let run = cli.run(&[&args[..], &["--fail-fast"]].concat());
+    assert_eq!(run.code, Some(2), "{:?}", run);
+    assert_eq!(
+        run.stdout,
+        "✅ fourth\n❌ second: Got 400 Invalid translation\n❌ fifth: Not sent because an earlier chunk failed\n"
+    );
+    assert!(
+        run.stderr
+            .ends_with("Stopped after the first chunk with keys that failed\n"),
+        "{:?}",
+        run
+    );
+    assert!(lokalise.key_named("fifth").is_none());
+}
+
+#[test]
+fn the_project_can_come_from_the_env() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    let run = |project: &str, args: &[&str]| {
+        let output = cli
+            .command(args)
+            .env("LOKALISE_PROJECT", project)
+            .output()
+            .unwrap();
+        (
+            output.status.code(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    assert_eq!(run("App", &["languages"]), (Some(0), String::new()));
+    assert_eq!(
+        run("Ap", &["languages"]),
+        (
+            Some(1),
+            "No project name 'Ap' was found, did you mean 'App'?\n".to_string()
+        )
+    );
+    assert_eq!(
+        run("Ap", &["languages", "-p", "App"]),
+        (Some(0), String::new())
+    );
+    assert_eq!(
+        run("Ap", &["languages", "--project-id", "123.abc"]),
+        (Some(0), String::new())
+    );
+}
+
+#[test]
+fn verify_fails_for_keys_missing_after_creating_them() {
+    let lokalise = Arc::new(Lokalise::new(vec![]));
+    let handler = lokalise.clone();
+    let server = MockServer::start(move |req| {
+        let mut res = handler.handle(req);
+        if req.method == "GET" && req.path == keys_path() {
+            // Lokalise lost `body` somehow
+            let mut page: serde_json::Value = serde_json::from_str(&res.body).unwrap();
+            page["keys"]
+                .as_array_mut()
+                .unwrap()
+                .retain(|key| key["key_name"]["ios"] != "body");
+            res.body = page.to_string();
+        }
+        res
+    });
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - { key: title, translation: Title }
+          - { key: body, translation: Body }
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--verify", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(2), "{:?}", run);
+    assert_eq!(
+        run.stdout,
+        "✅ title\n❌ body: Not in the project after creating it\n"
+    );
+    assert!(lokalise.key_named("body").is_some());
+}
+
+#[test]
+fn results_go_to_stdout_and_notes_to_stderr() {
+    let (server, _) = MockServer::lokalise(vec![common::key(1, "title", &[])]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - { key: title, translation: Title }
+          - { key: body, translation: Body }
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--skip-existing", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "✅ body\n⏭️ title\n");
+    let notes = run.stderr.lines().collect::<Vec<_>>();
+    assert_eq!(notes[0], "1 of 2 keys already exist and were skipped");
+    assert!(notes[1].starts_with("Took "), "{:?}", run);
+    assert_eq!(notes.len(), 2);
+
+    let run = cli.run(&[
+        "-p",
+        "App",
+        "--skip-existing",
+        "--output",
+        "json",
+        "keys.yaml",
+    ]);
+    assert_eq!(run.stderr, "");
+    let summary: serde_json::Value = serde_json::from_str(&run.stdout).unwrap();
+    // `body` was created by the first run
+    assert_eq!(summary["skipped"], json!(["title", "body"]));
+
+    let run = cli.run(&["export", "-p", "App"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert!(run.stdout.contains("key: body"), "{:?}", run);
+    assert_eq!(run.stderr, "");
+}
+
+#[test]
+fn limit_only_sends_the_first_keys() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - { key: c_title, translation: C }
+          - { key: a_title, translation: A }
+          - { key: b_title, translation: B }
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--sort", "--limit", "2", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "✅ a_title\n✅ b_title\n");
+    let posts = server.requests_to("POST", &keys_path());
+    assert_eq!(posts.len(), 1);
+    assert_eq!(posts[0].json()["keys"].as_array().unwrap().len(), 2);
+    assert_eq!(lokalise.keys().len(), 2);
+}
+
+#[test]
+fn version_includes_the_package_version() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+
+    let run = cli.run(&["--version"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert!(
+        run.stdout.starts_with(&format!(
+            "lokalise-bulk-add-keys {} (",
+            env!("CARGO_PKG_VERSION")
+        )),
+        "{:?}",
+        run
+    );
+
+    let run = cli.run(&["--version", "--output", "json"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    let info: serde_json::Value = serde_json::from_str(&run.stdout).unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(info["git_commit"].is_string());
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn screenshots_are_uploaded_for_created_keys() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    let png = b"\x89PNG\r\n\x1a\n";
+    std::fs::write(cli.dir.path().join("title.png"), png).unwrap();
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+            screenshot: title.png
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--with-screenshots", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    let title_id = lokalise.key_named("title").unwrap()["key_id"].clone();
+    let uploads = server.requests_to(
+        "POST",
+        &format!("/api2/projects/{}/screenshots", PROJECT_ID),
+    );
+    assert_eq!(uploads.len(), 1);
+    let screenshot = &uploads[0].json()["screenshots"][0];
+    assert_eq!(
+        screenshot["data"],
+        format!("data:image/png;base64,{}", base64::encode(png))
+    );
+    assert_eq!(screenshot["title"], "title");
+    assert_eq!(screenshot["key_ids"], json!([title_id]));
+}
+
+#[test]
+fn ascii_prints_plain_markers() {
+    let (server, _) = MockServer::lokalise(vec![common::key(1, "title", &[])]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--ascii", "--skip-existing", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "[OK] body\n[SKIPPED] title\n");
+    assert!(!run.stdout.contains('✅'));
+}
+
+#[test]
+fn base_language_overrides_the_projects_one() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "keys:\n  - key: title\n    translation: Titre\n",
+    );
+
+    let run = cli.run(&["-p", "App", "--base-language", "fr", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert!(
+        run.stderr
+            .contains("Using `fr` as the base language instead of the project's `en`"),
+        "{:?}",
+        run
+    );
+    assert_eq!(
+        lokalise.key_named("title").unwrap()["translations"],
+        json!([{ "language_iso": "fr", "translation": "Titre" }])
+    );
+
+    let run = cli.run(&["-p", "App", "--base-language", "de", "keys.yaml"]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(run
+        .stderr
+        .contains("The language `de` isn't enabled in the project 'App'. It has: en, fr"));
+}
+
+#[test]
+fn diff_only_fails_when_keys_arent_in_lokalise_yet() {
+    let (server, lokalise) = MockServer::lokalise(vec![common::key(1, "title", &[])]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--diff-only", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(4), "{:?}", run);
+    assert!(
+        run.stdout.contains("Would create (1):\n  body\n"),
+        "{:?}",
+        run
+    );
+    assert!(run.stderr.contains("1 of 2 keys aren't in Lokalise yet"));
+    assert!(lokalise.key_named("body").is_none());
+    assert!(server.requests_to("POST", &keys_path()).is_empty());
+
+    cli.write(
+        "keys.yaml",
+        "keys:\n  - key: title\n    translation: Title\n",
+    );
+    let run = cli.run(&["-p", "App", "--diff-only", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+}
+
+#[test]
+fn the_token_can_be_read_from_a_file() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    let run = |args: &[&str]| {
+        let output = cli
+            .command(args)
+            .env("LOKALISE_API_TOKEN", "wrong-token")
+            .output()
+            .unwrap();
+        (
+            output.status.code(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    cli.write("token", &format!("{}\n", TOKEN));
+    assert_eq!(
+        run(&["languages", "-p", "App", "--token-file", "token"]),
+        (Some(0), String::new())
+    );
+    assert_eq!(
+        run(&[
+            "languages",
+            "-p",
+            "App",
+            "--token-file",
+            "token",
+            "--token",
+            "wrong-token"
+        ])
+        .0,
+        Some(3)
+    );
+
+    cli.write("empty", "\n");
+    assert_eq!(
+        run(&["languages", "-p", "App", "--token-file", "empty"]),
+        (Some(1), "The token file empty is empty\n".to_string())
+    );
+
+    let (code, stderr) = run(&["languages", "-p", "App", "--token-file", "missing"]);
+    assert_eq!(code, Some(1));
+    assert!(
+        stderr.starts_with("Failed to read the token file missing\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn tags_the_project_doesnt_use_yet_are_warned_about() {
+    let (server, lokalise) = MockServer::lokalise(vec![common::key(1, "title", &["onboarding"])]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: body
+            translation: Body
+            tags: [onboarding, onbaording]
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--warn-unknown-tags", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert!(
+        run.stderr
+            .contains("These tags aren't used in the project yet: `onbaording`"),
+        "{:?}",
+        run
+    );
+    assert!(lokalise.key_named("body").is_some());
+
+    // It's only checked when asked for
+    cli.write(
+        "keys.yaml",
+        "keys:\n  - key: footer\n    translation: Footer\n    tags: [footre]\n",
+    );
+    let run = cli.run(&["-p", "App", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert!(!run.stderr.contains("These tags"), "{:?}", run);
+}
+
+#[test]
+fn replace_deletes_the_existing_keys_and_creates_them_again() {
+    let (server, lokalise) = MockServer::lokalise(vec![common::key(1, "title", &["stale"])]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: New title
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--replace", "keys.yaml"]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(run.stderr.contains("Delete 1 keys from the project 'App'?"));
+    assert!(server.requests_to("DELETE", &keys_path()).is_empty());
+
+    let run = cli.run(&["-p", "App", "--replace", "--yes", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    let deletes = server.requests_to("DELETE", &keys_path());
+    assert_eq!(deletes.len(), 1);
+    assert_eq!(deletes[0].json()["keys"], json!([1]));
+    let title = lokalise.key_named("title").unwrap();
+    assert_ne!(title["key_id"], json!(1));
+    assert_eq!(title["tags"], json!([]));
+    assert_eq!(
+        title["translations"],
+        json!([{ "language_iso": "en", "translation": "New title" }])
+    );
+    assert!(lokalise.key_named("body").is_some());
+}
+
+#[test]
+fn custom_headers_are_sent_with_every_request() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+
+    let run = cli.run(&[
+        "languages",
+        "-p",
+        "App",
+        "--header",
+        "X-Routing-Token:abc",
+        "--header",
+        "X-Team: i18n",
+    ]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    for req in &requests {
+        assert_eq!(req.header("x-routing-token"), Some("abc"));
+        assert_eq!(req.header("x-team"), Some("i18n"));
+        assert!(req.header("x-api-token").is_some());
+    }
+
+    let run = cli.run(&["languages", "-p", "App", "--header", "X-Routing-Token"]);
+    assert_ne!(run.code, Some(0), "{:?}", run);
+    assert!(run
+        .stderr
+        .contains("Expected `KEY:VALUE`, got `X-Routing-Token`"));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn yes_skips_the_confirmation_for_large_batches() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--confirm-threshold", "1", "keys.yaml"]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(run.stderr.contains("Create 2 keys in the project 'App'?"));
+    assert!(run.stderr.contains("pass `--yes` to go ahead anyway"));
+    assert!(lokalise.keys().is_empty());
+
+    let run = cli.run(&["-p", "App", "--confirm-threshold", "2", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    cli.write(
+        "keys.yaml",
+        "keys:\n  - key: a\n    translation: A\n  - key: b\n    translation: B\n",
+    );
+
+    let run = cli.run(&[
+        "-p",
+        "App",
+        "--confirm-threshold",
+        "1",
+        "--yes",
+        "keys.yaml",
+    ]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "✅ a\n✅ b\n");
+    assert_eq!(lokalise.keys().len(), 4);
+}
+
+#[test]
+fn keys_differing_only_in_casing_from_existing_ones_are_warned_about() {
+    let (server, lokalise) = MockServer::lokalise(vec![common::key(1, "Title", &[])]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "keys:\n  - key: title\n    translation: Title\n",
+    );
+    let warning = "These keys only differ in casing from keys in the project: `title` (the project has `Title`)";
+
+    let run = cli.run(&["-p", "App", "--dry-run=remote", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert!(run.stderr.contains(warning), "{:?}", run);
+    assert!(run.stdout.contains("Would create (1):\n  title\n"));
+
+    let run = cli.run(&[
+        "-p",
+        "App",
+        "--check-case-collisions",
+        "--strict",
+        "keys.yaml",
+    ]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(run.stderr.contains(warning), "{:?}", run);
+    assert!(lokalise.key_named("title").is_none());
+
+    let run = cli.run(&["-p", "App", "--check-case-collisions", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert!(run.stderr.contains(warning), "{:?}", run);
+    assert!(lokalise.key_named("title").is_some());
+}
+
+#[test]
+fn small_inputs_only_fetch_the_keys_with_their_names() {
+    let (server, _) = MockServer::lokalise(vec![
+        common::key(1, "title", &[]),
+        common::key(2, "footer", &[]),
+    ]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--skip-existing", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "✅ body\n⏭️ title\n");
+    let lists = server.requests_to("GET", &keys_path());
+    assert_eq!(lists.len(), 1);
+    let mut names = lists[0]
+        .query("filter_keys")
+        .unwrap()
+        .split(',')
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    assert_eq!(names, ["body", "title"]);
+
+    let many = (0..101)
+        .map(|idx| format!("  - key: key_{}\n    translation: Text\n", idx))
+        .collect::<String>();
+    cli.write("keys.yaml", &format!("keys:\n{}", many));
+    let run = cli.run(&["-p", "App", "--diff-only", "keys.yaml"]);
+    assert_eq!(run.code, Some(4), "{:?}", run);
+    let lists = server.requests_to("GET", &keys_path());
+    assert_eq!(lists.len(), 2);
+    assert_eq!(lists[1].query("filter_keys"), None);
+}
+
+#[test]
+fn warnings_fail_the_run_before_anything_is_uploaded_with_strict() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: Title
+          - key: body
+            translation: ''
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--strict", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert_eq!(
+        run.stderr,
+        "These keys have empty translations: `body`\nFailing because of `--strict`\n"
+    );
+    assert!(server.requests_to("POST", &keys_path()).is_empty());
+    assert!(lokalise.keys().is_empty());
+
+    let run = cli.run(&["-p", "App", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert!(run
+        .stderr
+        .contains("Warning: These keys have empty translations: `body`"));
+    assert_eq!(lokalise.keys().len(), 2);
+}
+
+#[test]
+fn the_token_can_come_from_a_dotenv_file() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    cli.write(
+        ".env",
+        &format!(
+            "# Local settings\nexport LOKALISE_API_TOKEN=\"{}\"\nLOKALISE_PROJECT=App\n",
+            TOKEN
+        ),
+    );
+    let run = |token: Option<&str>| {
+        let mut command = cli.command(&["languages"]);
+        match token {
+            Some(token) => command.env("LOKALISE_API_TOKEN", token),
+            None => command.env_remove("LOKALISE_API_TOKEN"),
+        };
+        command.output().unwrap().status.code()
+    };
+
+    assert_eq!(run(None), Some(0));
+    // Real env vars win over the file
+    assert_eq!(run(Some("wrong-token")), Some(3));
+
+    cli.write(".env", "LOKALISE_API_TOKEN\n");
+    let run = cli.run(&["languages", "-p", "App"]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(run
+        .stderr
+        .contains("Failed to parse .env on line 1, expected `NAME=value`"));
+}
+
+#[test]
+fn keys_are_renamed_and_missing_ones_reported() {
+    let (server, lokalise) = MockServer::lokalise(vec![
+        common::key(1, "title", &[]),
+        common::key(2, "footer", &[]),
+    ]);
+    let cli = Cli::new(&server);
+    cli.write("renames.yaml", "title: heading\ngone: vanished\n");
+
+    let run = cli.run(&["rename", "-p", "App", "--dry-run", "renames.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(
+        run.stdout,
+        "Would rename (1):\n  title -> heading\nNot in the project (1):\n  gone\n"
+    );
+    assert!(server.requests_to("PUT", &keys_path()).is_empty());
+
+    let run = cli.run(&["rename", "-p", "App", "renames.yaml"]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(
+        run.stdout,
+        "✏️ title -> heading\n❓ gone (not in the project)\n"
+    );
+    let updates = server.requests_to("PUT", &keys_path());
+    assert_eq!(
+        updates[0].json()["keys"],
+        json!([{ "key_id": 1, "key_name": "heading" }])
+    );
+    assert_eq!(lokalise.key_named("heading").unwrap()["key_id"], json!(1));
+    assert!(lokalise.key_named("title").is_none());
+
+    cli.write("renames.yaml", "footer: heading\n");
+    let run = cli.run(&["rename", "-p", "App", "renames.yaml"]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(run.stderr.contains(
+        "The new names of these keys are already used in the project: `footer` -> `heading`"
+    ));
+}
+
+#[test]
+fn filling_missing_languages_leaves_the_translations_of_updated_keys_alone() {
+    let mut title = common::key(1, "title", &[]);
+    title["translations"] = json!([
+        { "language_iso": "en", "translation": "Title" },
+        { "language_iso": "fr", "translation": "Titre" },
+    ]);
+    let (server, lokalise) = MockServer::lokalise(vec![title]);
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: New title
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let run = cli.run(&[
+        "-p",
+        "App",
+        "--upsert",
+        "--fill-missing-languages",
+        "keys.yaml",
+    ]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    let updates = server.requests_to("PUT", &keys_path());
+    assert_eq!(
+        updates[0].json()["keys"][0]["translations"],
+        json!([{ "language_iso": "en", "translation": "New title" }])
+    );
+    assert_eq!(
+        lokalise.key_named("title").unwrap()["translations"],
+        json!([
+            { "language_iso": "fr", "translation": "Titre" },
+            { "language_iso": "en", "translation": "New title" },
+        ])
+    );
+    assert_eq!(
+        lokalise.key_named("body").unwrap()["translations"],
+        json!([
+            { "language_iso": "en", "translation": "Body" },
+            { "language_iso": "fr", "translation": "Body" },
+        ])
+    );
+}
+
+#[test]
+fn replace_creates_again_the_keys_deleted_from_a_chunk_with_locked_keys() {
+    let lokalise = Arc::new(Lokalise::new(vec![
+        common::key(1, "title", &[]),
+        common::key(2, "body", &[]),
+    ]));
+    let handle = lokalise.clone();
+    let server = MockServer::start(move |req| {
+        if req.method != "DELETE" {
+            return handle.handle(req);
+        }
+        // `body` is locked, so only `title` is deleted
+        let title = json!({ "keys": [1] }).to_string();
+        handle.handle(&common::Request {
+            body: title,
+            ..req.clone()
+        });
+        Response::json(json!({ "project_id": PROJECT_ID, "keys_removed": true, "keys_locked": 1 }))
+    });
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: New title
+          - key: body
+            translation: New body
+        ",
+    );
+
+    let run = cli.run(&["-p", "App", "--replace", "--yes", "keys.yaml"]);
+
+    assert_eq!(run.code, Some(2), "{:?}", run);
+    assert_eq!(
+        run.stdout,
+        "✅ title\n❌ body: Failed to delete the existing key to replace it\n"
+    );
+    let title = lokalise.key_named("title").unwrap();
+    assert_ne!(title["key_id"], json!(1));
+    assert_eq!(lokalise.key_named("body").unwrap()["key_id"], json!(2));
+}
+
+#[test]
+fn replace_creates_again_the_keys_deleted_before_a_chunk_failed() {
+    let lokalise = Arc::new(Lokalise::new(vec![
+        common::key(1, "title", &[]),
+        common::key(2, "body", &[]),
+    ]));
+    let handle = lokalise.clone();
+    let server = MockServer::start(move |req| {
+        if req.method == "DELETE" && req.json()["keys"] == json!([2]) {
+            return Response::error(400, "Something went wrong");
+        }
+        handle.handle(req)
+    });
+    let cli = Cli::new(&server);
+    cli.write(
+        "keys.yaml",
+        "
+        keys:
+          - key: title
+            translation: New title
+          - key: body
+            translation: New body
+        ",
+    );
+
+    let run = cli.run(&[
+        "-p",
+        "App",
+        "--replace",
+        "--yes",
+        "--chunk-size",
+        "1",
+        "keys.yaml",
+    ]);
+
+    assert_eq!(run.code, Some(3), "{:?}", run);
+    assert!(run.stdout.contains("✅ title\n"), "{:?}", run);
+    assert!(
+        run.stderr.contains("Got 400 Something went wrong"),
+        "{:?}",
+        run
+    );
+    assert_ne!(lokalise.key_named("title").unwrap()["key_id"], json!(1));
+    assert_eq!(lokalise.key_named("body").unwrap()["key_id"], json!(2));
+}
+
+#[test]
+fn the_elapsed_time_is_only_reported_for_successful_runs() {
+    let lokalise = Arc::new(Lokalise::new(vec![common::key(1, "taken", &[])]));
+    let handle = lokalise.clone();
+    // Listing keys misses `taken`, so creating it fails
+    let server = MockServer::start(move |req| match req.method.as_str() {
+        "GET" if req.path == keys_path() => {
+            Response::json(json!({ "project_id": PROJECT_ID, "keys": [] }))
+        }
+        _ => handle.handle(req),
+    });
+    let cli = Cli::new(&server);
+
+    cli.write(
+        "keys.yaml",
+        "keys:\n  - key: title\n    translation: Title\n",
+    );
+    let run = cli.run(&["-p", "App", "--output", "json", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    let summary: serde_json::Value = serde_json::from_str(&run.stdout).unwrap();
+    assert!(summary["elapsed_ms"].is_u64(), "{}", summary);
+    assert!(summary["keys_per_second"].is_f64(), "{}", summary);
+
+    cli.write(
+        "keys.yaml",
+        "keys:\n  - key: taken\n    translation: Taken\n",
+    );
+    let run = cli.run(&["-p", "App", "--output", "json", "keys.yaml"]);
+    assert_eq!(run.code, Some(2), "{:?}", run);
+    let summary: serde_json::Value = serde_json::from_str(&run.stdout).unwrap();
+    assert_eq!(summary["counts"]["failed"], json!(1));
+    assert!(summary.get("elapsed_ms").is_none(), "{}", summary);
+}
+
+#[test]
+fn creating_more_than_100_keys_asks_for_confirmation_by_default() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    let cli = Cli::new(&server);
+    let keys = (0..101)
+        .map(|idx| format!("  - key: key_{}\n    translation: Text\n", idx))
+        .collect::<String>();
+    cli.write("keys.yaml", &format!("keys:\n{}", keys));
+
+    let run = cli.run(&["-p", "App", "keys.yaml"]);
+    assert_eq!(run.code, Some(1), "{:?}", run);
+    assert!(run.stderr.contains("Create 101 keys in the project 'App'?"));
+    assert!(lokalise.keys().is_empty());
+
+    let run = cli.run(&["-p", "App", "--confirm-threshold", "101", "keys.yaml"]);
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(lokalise.keys().len(), 101);
+}
+
+#[test]
+fn renames_use_the_key_prefix_and_lowercasing() {
+    let (server, lokalise) = MockServer::lokalise(vec![common::key(1, "app.title", &[])]);
+    let cli = Cli::new(&server);
+    cli.write("renames.json", r#"{ " Title ": "Heading" }"#);
+
+    let run = cli.run(&[
+        "rename",
+        "-p",
+        "App",
+        "--key-prefix",
+        "app.",
+        "--lowercase-keys",
+        "renames.json",
+    ]);
+
+    assert_eq!(run.code, Some(0), "{:?}", run);
+    assert_eq!(run.stdout, "✏️ app.title -> app.heading\n");
+    assert_eq!(
+        lokalise.key_named("app.heading").unwrap()["key_id"],
+        json!(1)
+    );
+}
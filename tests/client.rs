@@ -0,0 +1,624 @@
+mod common;
+
+use common::{Lokalise, MockServer, Response, PROJECT_ID, TOKEN};
+use futures::future;
+use indicatif::ProgressBar;
+use lokalise_bulk_add_keys::{
+    BatchOptions, ClientConfig, Data, KeyToAdd, LokaliseClient, LokaliseError, Project, ProxyConfig,
+};
+use serde_json::json;
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+fn config(server: &MockServer) -> ClientConfig {
+    ClientConfig {
+        base_url: server.base_url(),
+        max_retries: 3,
+        timeout: Duration::from_secs(5),
+        proxy: ProxyConfig::Disabled,
+        max_idle_connections: 1,
+        headers: vec![],
+    }
+}
+
+fn client(server: &MockServer) -> LokaliseClient {
+    LokaliseClient::new(TOKEN.parse().unwrap(), config(server)).unwrap()
+}
+
+fn project() -> Project {
+    serde_json::from_value(common::project()).unwrap()
+}
+
+#[tokio::test]
+async fn languages_are_parsed() {
+    let (server, _) = MockServer::lokalise(vec![]);
+
+    let languages = client(&server).languages(&project()).await.unwrap();
+
+    let languages = languages
+        .iter()
+        .map(|language| (language.lang_iso.as_str(), language.lang_name.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(languages, [("en", "English"), ("fr", "French")]);
+    let requests = server.requests();
+    assert_eq!(
+        requests[0].path,
+        format!("/api2/projects/{}/languages", PROJECT_ID)
+    );
+}
+
+#[tokio::test]
+async fn keys_are_fetched_from_every_page() {
+    let server = MockServer::start(|req| {
+        let page = req.query("page").unwrap_or("1");
+        let key = common::key(page.parse().unwrap(), &format!("key_{}", page), &[]);
+        Response::json(json!({ "project_id": PROJECT_ID, "keys": [key] }))
+            .with_header("x-pagination-total-count", "2500")
+    });
+
+    let keys = client(&server)
+        .all_keys(&project(), NonZeroUsize::new(2).unwrap(), None)
+        .await
+        .unwrap();
+
+    let mut names = keys.keys().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["key_1", "key_2", "key_3"]);
+    let mut pages = server
+        .requests()
+        .iter()
+        .map(|req| req.query("page").map(str::to_string))
+        .collect::<Vec<_>>();
+    pages.sort();
+    assert_eq!(pages, [None, Some("2".to_string()), Some("3".to_string())]);
+}
+
+fn batch() -> BatchOptions {
+    BatchOptions::new(
+        NonZeroUsize::new(500).unwrap(),
+        NonZeroUsize::new(1).unwrap(),
+        false,
+        ProgressBar::hidden(),
+    )
+}
+
+fn keys(yaml: &str) -> Vec<KeyToAdd> {
+    serde_yaml::from_str::<Data>(yaml).unwrap().keys
+}
+
+#[tokio::test]
+async fn keys_are_created() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    let keys = keys(
+        "
+        keys:
+          - key: title
+            translation: Title
+            tags: [onboarding]
+          - key: apples
+            translations: { one: One apple, other: '{count} apples' }
+        ",
+    );
+
+    let summary = client(&server)
+        .create_keys(&project(), "en", &keys, &batch())
+        .await
+        .unwrap();
+
+    let created = summary
+        .created
+        .iter()
+        .map(|key| (key.key.as_str(), key.key_id))
+        .collect::<Vec<_>>();
+    assert_eq!(created, [("title", 1), ("apples", 2)]);
+    assert!(summary.failed.is_empty());
+    let title = lokalise.key_named("title").unwrap();
+    assert_eq!(title["tags"], json!(["onboarding"]));
+    assert_eq!(
+        title["translations"],
+        json!([{ "language_iso": "en", "translation": "Title" }])
+    );
+    let apples = lokalise.key_named("apples").unwrap();
+    assert_eq!(apples["is_plural"], json!(true));
+    assert_eq!(
+        server.requests()[0].path,
+        format!("/api2/projects/{}/keys", PROJECT_ID)
+    );
+}
+
+#[tokio::test]
+async fn keys_are_fetched_with_cursors() {
+    let server = MockServer::start(|req| {
+        let (key, next_cursor) = match req.query("cursor") {
+            None => (common::key(1, "title", &[]), "eyIxIjo0fQ=="),
+            Some("eyIxIjo0fQ==") => (common::key(2, "body", &[]), ""),
+            Some(cursor) => panic!("unexpected cursor {}", cursor),
+        };
+        Response::json(json!({ "project_id": PROJECT_ID, "keys": [key] }))
+            .with_header("x-pagination-next-cursor", next_cursor)
+    });
+
+    let keys = client(&server)
+        .all_keys(&project(), NonZeroUsize::new(1).unwrap(), None)
+        .await
+        .unwrap();
+
+    assert_eq!(keys["title"].id, 1);
+    assert_eq!(keys["body"].id, 2);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests
+        .iter()
+        .all(|req| req.query("pagination") == Some("cursor")));
+    assert_eq!(requests[1].query("cursor"), Some("eyIxIjo0fQ=="));
+}
+
+#[tokio::test]
+async fn keys_lokalise_rejects_are_reported_with_the_reason() {
+    let (server, _) = MockServer::lokalise(vec![common::key(1, "title", &[])]);
+    let keys = keys(
+        "
+        keys:
+          - key: title
+            translation: Title
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let summary = client(&server)
+        .create_keys(&project(), "en", &keys, &batch())
+        .await
+        .unwrap();
+
+    let created = summary
+        .created
+        .iter()
+        .map(|key| (key.key.as_str(), key.key_id))
+        .collect::<Vec<_>>();
+    assert_eq!(created, [("body", 2)]);
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].key, "title");
+    assert_eq!(
+        summary.failed[0].reason.as_deref(),
+        Some("This key name is already taken")
+    );
+}
+
+#[tokio::test]
+async fn requests_go_through_the_proxy() {
+    let (proxy, _) = MockServer::lokalise(vec![]);
+    let client = LokaliseClient::new(
+        TOKEN.parse().unwrap(),
+        ClientConfig {
+            base_url: "http://lokalise.invalid/api2".to_string(),
+            proxy: ProxyConfig::Url(proxy.url.clone()),
+            ..config(&proxy)
+        },
+    )
+    .unwrap();
+
+    let projects = client.projects().await.unwrap();
+
+    assert_eq!(projects[0].id, PROJECT_ID);
+    let requests = proxy.requests();
+    assert_eq!(requests[0].path, "/api2/projects");
+    assert_eq!(requests[0].header("host"), Some("lokalise.invalid"));
+}
+
+#[tokio::test]
+async fn disabling_the_proxy_ignores_the_env_vars() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    // Nothing listens on port 1, and only this test uses the env vars
+    std::env::set_var("HTTP_PROXY", "http://127.0.0.1:1");
+
+    let projects = client(&server).projects().await;
+
+    std::env::remove_var("HTTP_PROXY");
+    assert_eq!(projects.unwrap()[0].id, PROJECT_ID);
+    assert!(LokaliseClient::new(
+        TOKEN.parse().unwrap(),
+        ClientConfig {
+            proxy: ProxyConfig::Url("not a url".to_string()),
+            ..config(&server)
+        },
+    )
+    .is_err());
+}
+
+#[tokio::test]
+async fn a_wrong_token_fails_the_token_check() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    let wrong = LokaliseClient::new("wrong-token".parse().unwrap(), config(&server)).unwrap();
+
+    let err = wrong.check_token().await.unwrap_err();
+
+    assert!(matches!(err, LokaliseError::Unauthorized), "{:?}", err);
+    assert!(err.to_string().contains("Got 401 unauthorized"), "{}", err);
+    assert_eq!(server.requests().len(), 1);
+    client(&server).check_token().await.unwrap();
+}
+
+#[tokio::test]
+async fn requests_are_retried_when_the_connection_is_dropped() {
+    let (server, _) = MockServer::lokalise(vec![]);
+    server.reset_connections(1);
+
+    let projects = client(&server).projects().await.unwrap();
+
+    assert_eq!(projects[0].id, PROJECT_ID);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn the_ids_of_created_keys_are_in_the_summary() {
+    let (server, _) = MockServer::lokalise(vec![common::key(41, "existing", &[])]);
+    let keys = keys("keys:\n  - key: title\n    translation: Title\n");
+
+    let summary = client(&server)
+        .create_keys(&project(), "en", &keys, &batch())
+        .await
+        .unwrap();
+
+    let json = summary.to_json();
+    assert_eq!(json["created"], json!([{ "key": "title", "key_id": 42 }]));
+    assert_eq!(json["counts"]["created"], json!(1));
+}
+
+#[tokio::test]
+async fn requests_slow_down_when_the_rate_limit_is_almost_used_up() {
+    async fn wait_between_requests(remaining: &'static str) -> Duration {
+        let server = MockServer::start(move |_| {
+            Response::json(json!({ "projects": [common::project()] }))
+                .with_header("x-ratelimit-remaining", remaining)
+        });
+        let client = client(&server);
+
+        client.projects().await.unwrap();
+        client.projects().await.unwrap();
+
+        let requests = server.requests();
+        requests[1].received - requests[0].received
+    }
+
+    let waited = wait_between_requests("1").await;
+    assert!(waited >= Duration::from_secs(1), "{:?}", waited);
+    let waited = wait_between_requests("100").await;
+    assert!(waited < Duration::from_secs(1), "{:?}", waited);
+}
+
+#[tokio::test]
+async fn updates_lokalise_partly_rejects_are_reported() {
+    let (server, _) = MockServer::lokalise(vec![common::key(1, "title", &[])]);
+    let mut keys = keys(
+        "
+        keys:
+          - key: title
+            translation: New title
+          - key: gone
+            translation: Gone
+        ",
+    )
+    .into_iter();
+    let keys = [(1, keys.next().unwrap()), (99, keys.next().unwrap())];
+
+    let summary = client(&server)
+        .update_keys(&project(), "en", &keys, &batch())
+        .await
+        .unwrap();
+
+    let updated = summary
+        .updated
+        .iter()
+        .map(|key| (key.key.as_str(), key.key_id))
+        .collect::<Vec<_>>();
+    assert_eq!(updated, [("title", 1)]);
+    assert_eq!(summary.failed[0].key, "gone");
+    assert_eq!(summary.failed[0].reason.as_deref(), Some("Key not found"));
+}
+
+#[tokio::test]
+async fn errors_are_matched_to_keys_by_their_per_platform_name() {
+    let server = MockServer::start(|_| {
+        Response::json(json!({
+            "project_id": PROJECT_ID,
+            "keys": [common::key(1, "body", &[])],
+            "errors": [{
+                "message": "This key name is already taken",
+                "code": 400,
+                "key": { "key_name": { "ios": "title", "android": "title", "web": "title", "other": "title" } },
+            }],
+        }))
+    });
+    let keys = keys(
+        "
+        keys:
+          - key: title
+            translation: Title
+            platform_names: { web: titleWeb }
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let summary = client(&server)
+        .create_keys(&project(), "en", &keys, &batch())
+        .await
+        .unwrap();
+
+    assert_eq!(summary.created[0].key, "body");
+    assert_eq!(summary.failed[0].key, "title");
+    assert_eq!(
+        summary.failed[0].reason.as_deref(),
+        Some("This key name is already taken")
+    );
+}
+
+#[tokio::test]
+async fn server_errors_are_retried() {
+    let failures = AtomicUsize::new(2);
+    let server = MockServer::start(move |_| {
+        if failures.load(Ordering::SeqCst) > 0 {
+            failures.fetch_sub(1, Ordering::SeqCst);
+            Response::text(503, "Service Unavailable").with_header("retry-after", "0")
+        } else {
+            Response::json(json!({ "projects": [common::project()] }))
+        }
+    });
+
+    let projects = client(&server).projects().await.unwrap();
+
+    assert_eq!(projects[0].id, PROJECT_ID);
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn server_errors_give_up_after_max_retries() {
+    let server = MockServer::start(|_| {
+        Response::text(503, "Service Unavailable").with_header("retry-after", "0")
+    });
+
+    let err = client(&server).projects().await.unwrap_err();
+
+    assert!(
+        matches!(err, LokaliseError::ServerError { retries: 3, .. }),
+        "{:?}",
+        err
+    );
+    assert!(err
+        .to_string()
+        .contains("Lokalise server error (503 Service Unavailable), gave up after 3 retries"));
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[tokio::test]
+async fn only_the_fields_that_are_used_are_fetched() {
+    let (server, _) = MockServer::lokalise(vec![common::key(1, "title", &[])]);
+
+    let keys = client(&server)
+        .all_keys(&project(), NonZeroUsize::new(1).unwrap(), None)
+        .await
+        .unwrap();
+
+    assert_eq!(keys["title"].id, 1);
+    let requests = server.requests();
+    assert_eq!(requests[0].query("include_translations"), Some("0"));
+    assert_eq!(requests[0].query("include_comments"), Some("0"));
+    assert_eq!(requests[0].query("include_screenshots"), Some("0"));
+    assert_eq!(requests[0].query("disable_references"), Some("1"));
+}
+
+#[tokio::test]
+async fn keys_created_by_a_request_that_timed_out_are_not_sent_again() {
+    let lokalise = Arc::new(Lokalise::new(vec![]));
+    let handle = lokalise.clone();
+    let timed_out = AtomicBool::new(false);
+    let server = MockServer::start(move |req| {
+        let res = handle.handle(req);
+        if req.method == "POST" && !timed_out.swap(true, Ordering::SeqCst) {
+            thread::sleep(Duration::from_secs(1));
+        }
+        res
+    });
+    let client = LokaliseClient::new(
+        TOKEN.parse().unwrap(),
+        ClientConfig {
+            timeout: Duration::from_millis(300),
+            ..config(&server)
+        },
+    )
+    .unwrap();
+    let keys = keys(
+        "
+        keys:
+          - key: title
+            translation: Title
+          - key: body
+            translation: Body
+        ",
+    );
+
+    let summary = client
+        .create_keys(&project(), "en", &keys, &batch())
+        .await
+        .unwrap();
+
+    let mut created = summary
+        .created
+        .iter()
+        .map(|key| (key.key.as_str(), key.key_id))
+        .collect::<Vec<_>>();
+    created.sort_unstable();
+    assert_eq!(created, [("body", 2), ("title", 1)]);
+    assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+    let keys_path = format!("/api2/projects/{}/keys", PROJECT_ID);
+    assert_eq!(server.requests_to("POST", &keys_path).len(), 1);
+    let lists = server.requests_to("GET", &keys_path);
+    assert_eq!(lists[0].query("filter_keys"), Some("title,body"));
+    assert_eq!(lokalise.keys().len(), 2);
+}
+
+#[tokio::test]
+async fn keys_are_sent_again_when_the_connection_dropped_before_they_were_created() {
+    let (server, lokalise) = MockServer::lokalise(vec![]);
+    server.reset_connections(1);
+    let keys = keys("keys:\n  - key: title\n    translation: Title\n");
+
+    let summary = client(&server)
+        .create_keys(&project(), "en", &keys, &batch())
+        .await
+        .unwrap();
+
+    assert_eq!(summary.created[0].key, "title");
+    assert!(summary.failed.is_empty());
+    assert_eq!(lokalise.keys().len(), 1);
+    let keys_path = format!("/api2/projects/{}/keys", PROJECT_ID);
+    assert_eq!(server.requests_to("GET", &keys_path).len(), 1);
+    assert_eq!(server.requests_to("POST", &keys_path).len(), 1);
+}
+
+#[tokio::test]
+async fn screenshot_uploads_that_timed_out_are_not_retried() {
+    let server = MockServer::start(|_| {
+        thread::sleep(Duration::from_secs(1));
+        Response::json(json!({ "screenshots": [], "errors": [] }))
+    });
+    let client = LokaliseClient::new(
+        TOKEN.parse().unwrap(),
+        ClientConfig {
+            timeout: Duration::from_millis(300),
+            ..config(&server)
+        },
+    )
+    .unwrap();
+
+    let err = client
+        .upload_screenshot(&project(), 1, "title", "image/png", b"\x89PNG")
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, LokaliseError::Timeout { .. }), "{:?}", err);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn keys_created_by_a_request_that_got_a_server_error_are_not_sent_again() {
+    let lokalise = Arc::new(Lokalise::new(vec![]));
+    let handle = lokalise.clone();
+    let failed = AtomicBool::new(false);
+    let server = MockServer::start(move |req| {
+        let res = handle.handle(req);
+        if req.method == "POST" && !failed.swap(true, Ordering::SeqCst) {
+            // Like a proxy timing out after Lokalise created the keys
+            return Response::text(502, "Bad Gateway").with_header("retry-after", "0");
+        }
+        res
+    });
+    let keys = keys("keys:\n  - key: title\n    translation: Title\n");
+
+    let summary = client(&server)
+        .create_keys(&project(), "en", &keys, &batch())
+        .await
+        .unwrap();
+
+    assert_eq!(summary.created[0].key, "title");
+    assert!(summary.failed.is_empty(), "{:?}", summary.failed);
+    let keys_path = format!("/api2/projects/{}/keys", PROJECT_ID);
+    assert_eq!(server.requests_to("POST", &keys_path).len(), 1);
+    assert_eq!(lokalise.keys().len(), 1);
+}
+
+#[tokio::test]
+async fn creating_keys_gives_up_after_max_retries_of_server_errors() {
+    let server = MockServer::start(|req| match req.method.as_str() {
+        "POST" => Response::text(503, "Service Unavailable"),
+        _ => Response::json(json!({ "project_id": PROJECT_ID, "keys": [] })),
+    });
+    let client = LokaliseClient::new(
+        TOKEN.parse().unwrap(),
+        ClientConfig {
+            max_retries: 1,
+            ..config(&server)
+        },
+    )
+    .unwrap();
+    let keys = keys("keys:\n  - key: title\n    translation: Title\n");
+
+    let err = client
+        .create_keys(&project(), "en", &keys, &batch())
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(err, LokaliseError::ServerError { retries: 1, .. }),
+        "{:?}",
+        err
+    );
+    let keys_path = format!("/api2/projects/{}/keys", PROJECT_ID);
+    assert_eq!(server.requests_to("POST", &keys_path).len(), 2);
+    assert_eq!(server.requests_to("GET", &keys_path).len(), 1);
+}
+
+#[tokio::test]
+async fn screenshot_uploads_that_got_a_server_error_are_not_retried() {
+    let server = MockServer::start(|_| {
+        Response::text(503, "Service Unavailable").with_header("retry-after", "0")
+    });
+
+    let err = client(&server)
+        .upload_screenshot(&project(), 1, "title", "image/png", b"\x89PNG")
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(err, LokaliseError::ServerError { retries: 0, .. }),
+        "{:?}",
+        err
+    );
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn concurrent_requests_wait_for_the_rate_limit_to_reset_once_theyd_use_it_up() {
+    let reset = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 2;
+    let server = MockServer::start(move |_| {
+        Response::json(json!({ "projects": [common::project()] }))
+            .with_header("x-ratelimit-limit", "6")
+            .with_header("x-ratelimit-remaining", "3")
+            .with_header("x-ratelimit-reset", &reset.to_string())
+    });
+    let client = client(&server);
+    client.projects().await.unwrap();
+
+    let results = future::join_all((0..4).map(|_| client.projects())).await;
+
+    assert!(results.iter().all(Result::is_ok));
+    let requests = server.requests();
+    let waited = requests[1..]
+        .iter()
+        .map(|req| req.received - requests[0].received)
+        .collect::<Vec<_>>();
+    // One request is left for other clients, and the rest wait until the reset
+    let right_away = waited
+        .iter()
+        .filter(|waited| **waited < Duration::from_millis(500))
+        .count();
+    assert_eq!(right_away, 2, "{:?}", waited);
+    assert!(
+        waited.iter().all(|waited| *waited < Duration::from_millis(500)
+            || *waited >= Duration::from_secs(1)),
+        "{:?}",
+        waited
+    );
+}
@@ -0,0 +1,549 @@
+//! A stand-in for the Lokalise API that the client and the binary are pointed at in tests.
+
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+
+pub const TOKEN: &str = "test-token";
+pub const PROJECT_ID: &str = "123.abc";
+pub const PROJECT_NAME: &str = "App";
+
+/// A request the mock server got.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    /// Keyed by lowercase name
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    /// When the request came in
+    pub received: Instant,
+}
+
+impl Request {
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|value| value.as_str())
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn json(body: Value) -> Self {
+        Response::status(200, body)
+    }
+
+    pub fn status(status: u16, body: Value) -> Self {
+        Response {
+            status,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: body.to_string(),
+        }
+    }
+
+    /// The error body Lokalise responds with when a whole request fails.
+    pub fn error(status: u16, message: &str) -> Self {
+        Response::status(
+            status,
+            json!({ "error": { "code": status, "message": message } }),
+        )
+    }
+
+    pub fn text(status: u16, body: &str) -> Self {
+        Response {
+            status,
+            headers: vec![],
+            body: body.to_string(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+/// An HTTP server on a random port answering every request with `handler`, one connection per
+/// request.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+    resets: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let resets = Arc::new(AtomicUsize::new(0));
+        let handler = Arc::new(handler) as Arc<Handler>;
+
+        let server = MockServer {
+            url,
+            requests: requests.clone(),
+            resets: resets.clone(),
+        };
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let reset = resets
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_ok();
+                let handler = handler.clone();
+                let requests = requests.clone();
+                thread::spawn(move || {
+                    if reset {
+                        // Closing the socket with the request still unread makes the OS reset
+                        // the connection
+                        thread::sleep(Duration::from_millis(100));
+                        drop(stream);
+                    } else {
+                        serve(stream, &*handler, &requests);
+                    }
+                });
+            }
+        });
+        server
+    }
+
+    /// A server acting like a Lokalise project with these keys.
+    pub fn lokalise(keys: Vec<Value>) -> (Self, Arc<Lokalise>) {
+        let lokalise = Arc::new(Lokalise::new(keys));
+        let handle = lokalise.clone();
+        (MockServer::start(move |req| handle.handle(req)), lokalise)
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("{}/api2", self.url)
+    }
+
+    /// Reset the next `count` connections without answering them.
+    pub fn reset_connections(&self, count: usize) {
+        self.resets.store(count, Ordering::SeqCst);
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn requests_to(&self, method: &str, path: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|req| req.method == method && req.path == path)
+            .collect()
+    }
+}
+
+fn serve(stream: TcpStream, handler: &Handler, requests: &Mutex<Vec<Request>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+        return;
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default();
+    // Requests sent through a proxy have the whole URL in them
+    let target = match target.strip_prefix("http://") {
+        Some(rest) => &rest[rest.find('/').unwrap_or(rest.len())..],
+        None => target,
+    };
+    let (path, query) = match target.find('?') {
+        Some(idx) => (&target[..idx], parse_query(&target[idx + 1..])),
+        None => (target, vec![]),
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(idx) = line.find(':') {
+            headers.insert(
+                line[..idx].trim().to_lowercase(),
+                line[idx + 1..].trim().to_string(),
+            );
+        }
+    }
+    let len = headers
+        .get("content-length")
+        .and_then(|len| len.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).unwrap();
+
+    let request = Request {
+        method,
+        path: path.to_string(),
+        query,
+        headers,
+        body: String::from_utf8(body).unwrap(),
+        received: Instant::now(),
+    };
+    requests.lock().unwrap().push(request.clone());
+    let response = handler(&request);
+
+    let mut stream = stream;
+    let mut head = format!("HTTP/1.1 {} Mock\r\n", response.status);
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "content-length: {}\r\nconnection: close\r\n\r\n",
+        response.body.len()
+    ));
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(response.body.as_bytes());
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(idx) => (decode(&pair[..idx]), decode(&pair[idx + 1..])),
+            None => (decode(pair), String::new()),
+        })
+        .collect()
+}
+
+fn decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = vec![];
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'+' => decoded.push(b' '),
+            b'%' if idx + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[idx + 1..idx + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                idx += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        idx += 1;
+    }
+    String::from_utf8(decoded).unwrap()
+}
+
+/// A key as Lokalise returns it, with the same name on every platform.
+pub fn key(key_id: u64, name: &str, tags: &[&str]) -> Value {
+    json!({
+        "key_id": key_id,
+        "key_name": { "ios": name, "android": name, "web": name, "other": name },
+        "tags": tags,
+        "platforms": ["ios", "android", "web", "other"],
+    })
+}
+
+pub fn project() -> Value {
+    json!({
+        "project_id": PROJECT_ID,
+        "name": PROJECT_NAME,
+        "base_language_iso": "en",
+    })
+}
+
+/// A Lokalise project with English as the base language and French enabled, good enough for
+/// the requests the client makes.
+pub struct Lokalise {
+    keys: Mutex<Vec<Value>>,
+}
+
+impl Lokalise {
+    pub fn new(keys: Vec<Value>) -> Self {
+        Lokalise {
+            keys: Mutex::new(keys),
+        }
+    }
+
+    pub fn keys(&self) -> Vec<Value> {
+        self.keys.lock().unwrap().clone()
+    }
+
+    pub fn key_named(&self, name: &str) -> Option<Value> {
+        self.keys()
+            .into_iter()
+            .find(|key| key["key_name"]["ios"] == name)
+    }
+
+    pub fn handle(&self, req: &Request) -> Response {
+        if req.header("x-api-token") != Some(TOKEN) {
+            return Response::error(401, "Invalid `X-Api-Token` header");
+        }
+
+        let keys_path = format!("/api2/projects/{}/keys", PROJECT_ID);
+        match (req.method.as_str(), req.path.as_str()) {
+            ("GET", "/api2/projects") => Response::json(json!({ "projects": [project()] })),
+            ("GET", path) if path == format!("/api2/projects/{}", PROJECT_ID) => {
+                Response::json(project())
+            }
+            ("GET", path) if path == format!("/api2/projects/{}/languages", PROJECT_ID) => {
+                Response::json(json!({
+                    "languages": [
+                        { "lang_iso": "en", "lang_name": "English" },
+                        { "lang_iso": "fr", "lang_name": "French" },
+                    ],
+                }))
+            }
+            ("GET", path) if path == keys_path => self.list_keys(req),
+            ("POST", path) if path == keys_path => self.create_keys(req),
+            ("PUT", path) if path == keys_path => self.update_keys(req),
+            ("DELETE", path) if path == keys_path => self.delete_keys(req),
+            ("POST", path) if path == format!("/api2/projects/{}/screenshots", PROJECT_ID) => {
+                Response::json(json!({ "screenshots": [{ "screenshot_id": 1 }], "errors": [] }))
+            }
+            _ => Response::error(404, "Not Found"),
+        }
+    }
+
+    fn list_keys(&self, req: &Request) -> Response {
+        let names = req
+            .query("filter_keys")
+            .map(|names| names.split(',').map(str::to_string).collect::<Vec<_>>());
+        let page = req.query("page").map_or(1, |page| page.parse().unwrap());
+        let keys = self
+            .keys()
+            .into_iter()
+            .filter(|key| match &names {
+                Some(names) => key["key_name"]
+                    .as_object()
+                    .unwrap()
+                    .values()
+                    .any(|name| names.iter().any(|wanted| name == wanted)),
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        let total = keys.len();
+        let keys = keys
+            .into_iter()
+            .filter(|_| page == 1)
+            .map(|mut key| {
+                if req.query("include_translations") != Some("1") {
+                    key.as_object_mut().unwrap().remove("translations");
+                }
+                key
+            })
+            .collect::<Vec<_>>();
+
+        Response::json(json!({ "project_id": PROJECT_ID, "keys": keys }))
+            .with_header("x-pagination-total-count", &total.to_string())
+    }
+
+    fn create_keys(&self, req: &Request) -> Response {
+        let mut keys = self.keys.lock().unwrap();
+        let mut created = vec![];
+        let mut errors = vec![];
+
+        for payload in req.json()["keys"].as_array().unwrap() {
+            let key_name = match &payload["key_name"] {
+                Value::String(name) => {
+                    json!({ "ios": name, "android": name, "web": name, "other": name })
+                }
+                names => names.clone(),
+            };
+            let taken = key_name.as_object().unwrap().values().any(|name| {
+                keys.iter().any(|key| {
+                    key["key_name"]
+                        .as_object()
+                        .unwrap()
+                        .values()
+                        .any(|n| n == name)
+                })
+            });
+            if taken {
+                errors.push(json!({
+                    "message": "This key name is already taken",
+                    "code": 400,
+                    "key": { "key_name": payload["key_name"] },
+                }));
+                continue;
+            }
+
+            let key_id = keys
+                .iter()
+                .map(|key| key["key_id"].as_u64().unwrap())
+                .max()
+                .unwrap_or(0)
+                + 1;
+            let mut key = payload.clone();
+            key["key_id"] = json!(key_id);
+            key["key_name"] = key_name;
+            key["translations"] = stored_translations(&payload["translations"]);
+            keys.push(key.clone());
+            created.push(key);
+        }
+
+        Response::json(json!({ "project_id": PROJECT_ID, "keys": created, "errors": errors }))
+    }
+
+    fn update_keys(&self, req: &Request) -> Response {
+        let mut keys = self.keys.lock().unwrap();
+        let mut updated = vec![];
+        let mut errors = vec![];
+
+        for payload in req.json()["keys"].as_array().unwrap() {
+            let key = match keys
+                .iter_mut()
+                .find(|key| key["key_id"] == payload["key_id"])
+            {
+                Some(key) => key,
+                None => {
+                    errors.push(json!({
+                        "message": "Key not found",
+                        "code": 404,
+                        "key": { "key_id": payload["key_id"] },
+                    }));
+                    continue;
+                }
+            };
+
+            for (field, value) in payload.as_object().unwrap() {
+                key[field] = match (field.as_str(), value) {
+                    ("key_name", Value::String(name)) => {
+                        json!({ "ios": name, "android": name, "web": name, "other": name })
+                    }
+                    // Like Lokalise, only the languages that are sent are changed
+                    ("translations", translations) => {
+                        let mut merged =
+                            key["translations"].as_array().cloned().unwrap_or_default();
+                        for translation in stored_translations(translations).as_array().unwrap() {
+                            merged.retain(|old| old["language_iso"] != translation["language_iso"]);
+                            merged.push(translation.clone());
+                        }
+                        json!(merged)
+                    }
+                    _ => value.clone(),
+                };
+            }
+            updated.push(key.clone());
+        }
+
+        Response::json(json!({ "project_id": PROJECT_ID, "keys": updated, "errors": errors }))
+    }
+
+    fn delete_keys(&self, req: &Request) -> Response {
+        let ids = req.json()["keys"].as_array().unwrap().clone();
+        self.keys
+            .lock()
+            .unwrap()
+            .retain(|key| !ids.contains(&key["key_id"]));
+        Response::json(json!({
+            "project_id": PROJECT_ID,
+            "keys_removed": true,
+            "keys_locked": 0,
+        }))
+    }
+}
+
+/// Lokalise returns the plural forms of a translation JSON encoded.
+fn stored_translations(translations: &Value) -> Value {
+    let translations = translations.as_array().cloned().unwrap_or_default();
+    json!(translations
+        .into_iter()
+        .map(|translation| {
+            let text = match &translation["translation"] {
+                Value::String(text) => text.clone(),
+                forms => forms.to_string(),
+            };
+            json!({ "language_iso": translation["language_iso"], "translation": text })
+        })
+        .collect::<Vec<_>>())
+}
+
+/// The binary, pointed at a mock server, run in an empty directory so no config file or `.env`
+/// is picked up.
+pub struct Cli {
+    pub dir: TempDir,
+    base_url: String,
+}
+
+/// The outcome of running the binary.
+#[derive(Debug)]
+pub struct Run {
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Cli {
+    pub fn new(server: &MockServer) -> Self {
+        Cli {
+            dir: tempfile::tempdir().unwrap(),
+            base_url: server.base_url(),
+        }
+    }
+
+    /// Write a file into the directory the binary is run in.
+    pub fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.dir.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    pub fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_lokalise-bulk-add-keys"));
+        command
+            .args(args)
+            .current_dir(self.dir.path())
+            .env("HOME", self.dir.path())
+            .env("LOKALISE_API_TOKEN", TOKEN)
+            .env("LOKALISE_API_BASE_URL", &self.base_url)
+            .env_remove("LOKALISE_PROJECT")
+            .env_remove("HTTPS_PROXY")
+            .env_remove("HTTP_PROXY")
+            .env_remove("https_proxy")
+            .env_remove("http_proxy")
+            .stdin(Stdio::null());
+        command
+    }
+
+    pub fn run(&self, args: &[&str]) -> Run {
+        let output = self.command(args).output().unwrap();
+        Run {
+            code: output.status.code(),
+            stdout: String::from_utf8(output.stdout).unwrap(),
+            stderr: String::from_utf8(output.stderr).unwrap(),
+        }
+    }
+}